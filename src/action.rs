@@ -0,0 +1,215 @@
+use std::fmt;
+
+use crate::{state::StateTransition, theme::ThemeName, ui::WidgetId};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ControlCommand {
+    StoreNvm,
+    LoadNvm,
+    AlarmsReset,
+    SspaReset,
+    SspaDisable,
+}
+
+impl ControlCommand {
+    pub fn bit(&self) -> u16 {
+        1 << (14 - *self as u16)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompileStep {
+    Build,
+    CleanBuild,
+    CleanBuildFlash,
+    BuildFlash,
+    Flash,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Quit,
+    Help,
+    OpenPalette,
+    ToggleZoom,
+    NextLayout,
+    SelectLayout(usize),
+    SelectTheme(ThemeName),
+    Connect,
+    Disconnect,
+    FocusWidget(StateTransition),
+    FocusNext,
+    FocusPrevious,
+    MoveSelection(StateTransition),
+    Activate,
+    EditThreshold(usize),
+    EditDac(usize),
+    EditOffset(usize),
+    EditTnr(usize),
+    WriteThreshold(usize, u16),
+    WriteDac(usize, u16),
+    WriteOffset(usize, u16),
+    ClearDac,
+    TogglePowerEnable,
+    TnrLaunch,
+    TnrStop,
+    TnrSave,
+    LoadPreset(usize),
+    Control(ControlCommand),
+    ToggleProtection(usize),
+    Compile(CompileStep),
+    HardReset,
+    AbortScript,
+    ScrollUp(usize),
+    ScrollDown(usize),
+    PageUp,
+    PageDown,
+    ScrollTop,
+    ToggleFollow,
+    ToggleTimestamps,
+    CycleFilter,
+    ToggleHex,
+    StartSearch,
+    NextMatch,
+    PreviousMatch,
+    MouseClick { column: u16, row: u16 },
+    MouseScroll { column: u16, row: u16, up: bool },
+}
+
+impl Action {
+    // everything that reaches the unit or its firmware, dimmed in read-only mode
+    pub fn writes(&self) -> bool {
+        match self {
+            Action::Compile(step) => step.flashes(),
+            action => matches!(
+                action,
+                Action::EditThreshold(_)
+                    | Action::EditDac(_)
+                    | Action::EditOffset(_)
+                    | Action::WriteThreshold(..)
+                    | Action::WriteDac(..)
+                    | Action::WriteOffset(..)
+                    | Action::ClearDac
+                    | Action::TogglePowerEnable
+                    | Action::TnrLaunch
+                    | Action::TnrStop
+                    | Action::Control(_)
+                    | Action::ToggleProtection(_)
+                    | Action::HardReset
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ControlCommand::StoreNvm => "store NVM",
+            ControlCommand::LoadNvm => "load NVM",
+            ControlCommand::AlarmsReset => "alarms reset",
+            ControlCommand::SspaReset => "SSPA reset",
+            ControlCommand::SspaDisable => "SSPA disable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl CompileStep {
+    pub fn flashes(&self) -> bool {
+        matches!(
+            self,
+            CompileStep::CleanBuildFlash | CompileStep::BuildFlash | CompileStep::Flash
+        )
+    }
+}
+
+impl fmt::Display for CompileStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompileStep::Build => "build",
+            CompileStep::CleanBuild => "clean & build",
+            CompileStep::CleanBuildFlash => "clean & build & flash",
+            CompileStep::BuildFlash => "build & flash",
+            CompileStep::Flash => "flash",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::EditThreshold(i) => write!(f, "edit threshold {}", i),
+            Action::EditDac(i) => write!(f, "edit DAC {}", i),
+            Action::EditOffset(i) => write!(f, "edit offset {}", i),
+            Action::EditTnr(i) => write!(f, "edit TnR {}", ["period", "pulse width", "count"][*i]),
+            Action::WriteThreshold(i, value) => write!(f, "write threshold {} = {}", i, value),
+            Action::WriteDac(i, value) => write!(f, "write DAC {} = {}", i, value),
+            Action::WriteOffset(i, value) => write!(f, "write offset {} = {}", i, value),
+            Action::ClearDac => write!(f, "clear all DACs"),
+            Action::TogglePowerEnable => write!(f, "toggle power enable"),
+            Action::TnrLaunch => write!(f, "launch TnR"),
+            Action::TnrStop => write!(f, "stop TnR"),
+            Action::TnrSave => write!(f, "save TnR preset"),
+            Action::LoadPreset(i) => write!(f, "load preset {}", i),
+            Action::Control(command) => write!(f, "{}", command),
+            Action::ToggleProtection(i) => write!(f, "toggle protection disable {}", i),
+            Action::Compile(step) => write!(f, "{}", step),
+            Action::HardReset => write!(f, "hard reset"),
+            Action::AbortScript => write!(f, "abort script"),
+            Action::Connect => write!(f, "connect"),
+            Action::Disconnect => write!(f, "disconnect"),
+            action => write!(f, "{:?}", action),
+        }
+    }
+}
+
+pub fn item_action(wid: WidgetId, item: usize) -> Option<Action> {
+    match (wid, item) {
+        (WidgetId::Registers, 0..=8) => Some(Action::EditThreshold(item)),
+        (WidgetId::HardReset, _) => Some(Action::HardReset),
+        (WidgetId::Ext, 0) => Some(Action::TogglePowerEnable),
+        (WidgetId::Ext, 2..=4) => Some(Action::EditTnr(item - 2)),
+        (WidgetId::Ext, 5) => Some(Action::TnrLaunch),
+        (WidgetId::Ext, 6) => Some(Action::TnrStop),
+        (WidgetId::Ext, 7) => Some(Action::TnrSave),
+        (WidgetId::ExtPresets, _) => Some(Action::LoadPreset(item)),
+        (WidgetId::Compile, 0) => Some(Action::Compile(CompileStep::Build)),
+        (WidgetId::Compile, 1) => Some(Action::Compile(CompileStep::CleanBuild)),
+        (WidgetId::Compile, 2) => Some(Action::Compile(CompileStep::CleanBuildFlash)),
+        (WidgetId::Compile, 3) => Some(Action::Compile(CompileStep::BuildFlash)),
+        (WidgetId::Compile, 4) => Some(Action::Compile(CompileStep::Flash)),
+        (WidgetId::Dac, 0..=7) => Some(Action::EditDac(item)),
+        (WidgetId::Dac, 8) => Some(Action::ClearDac),
+        (WidgetId::Offsets, 0..=7) => Some(Action::EditOffset(item)),
+        (WidgetId::Control, 0) => Some(Action::Control(ControlCommand::StoreNvm)),
+        (WidgetId::Control, 1) => Some(Action::Control(ControlCommand::LoadNvm)),
+        (WidgetId::Control, 2) => Some(Action::Control(ControlCommand::AlarmsReset)),
+        (WidgetId::Control, 3) => Some(Action::Control(ControlCommand::SspaReset)),
+        (WidgetId::Control, 4) => Some(Action::Control(ControlCommand::SspaDisable)),
+        (WidgetId::Control, 5..=9) => Some(Action::ToggleProtection(item - 5)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_flashing_compile_steps_write() {
+        assert!(!Action::Compile(CompileStep::Build).writes());
+        assert!(!Action::Compile(CompileStep::CleanBuild).writes());
+        assert!(Action::Compile(CompileStep::BuildFlash).writes());
+        assert!(Action::Compile(CompileStep::Flash).writes());
+    }
+
+    #[test]
+    fn only_device_actions_write() {
+        assert!(Action::EditDac(0).writes());
+        assert!(Action::HardReset.writes());
+        assert!(!Action::EditTnr(0).writes());
+        assert!(!Action::LoadPreset(0).writes());
+        assert!(!Action::Help.writes());
+    }
+}
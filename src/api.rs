@@ -0,0 +1,428 @@
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{
+        broadcast,
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot, Notify,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    action::Action,
+    diagnostics::DiagnosticsSender,
+    palette::CONTROL_COMMANDS,
+    sspa::{
+        find_register, is_writable, DAC_ADDRESS, OFFSETS_ADDRESS, PROTECTION_NAMES,
+        THRESHOLDS_ADDRESS,
+    },
+};
+
+const EVENT_BACKLOG: usize = 256;
+
+pub enum Call {
+    Get(Option<String>),
+    Status,
+    Action(Action),
+    TnrLaunch(Option<[u16; 3]>),
+    LoadPreset(String),
+}
+
+pub type Reply = oneshot::Receiver<Result<Value, String>>;
+
+pub struct Request {
+    pub source: &'static str,
+    pub client: String,
+    pub method: String,
+    pub params: Value,
+    reply: oneshot::Sender<Result<Value, String>>,
+}
+
+impl Request {
+    pub fn new(
+        source: &'static str,
+        client: String,
+        method: &str,
+        params: Value,
+    ) -> (Request, Reply) {
+        let (reply, response) = oneshot::channel();
+        let request = Request {
+            source,
+            client,
+            method: method.to_string(),
+            params,
+            reply,
+        };
+        (request, response)
+    }
+
+    pub fn respond(self, result: Result<Value, String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+pub struct Api {
+    path: PathBuf,
+    // only the session that bound the socket may remove it
+    bound: Arc<AtomicBool>,
+    rx: UnboundedReceiver<Request>,
+    events: broadcast::Sender<String>,
+    task: JoinHandle<()>,
+}
+
+impl Api {
+    pub fn new(path: &Path, diagnostics: DiagnosticsSender, wakeup: Arc<Notify>) -> Api {
+        let (tx, rx) = unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let subscribers = events.clone();
+        let socket = path.to_path_buf();
+        let bound = Arc::new(AtomicBool::new(false));
+        let listening = bound.clone();
+        let task = tokio::spawn(async move {
+            if UnixStream::connect(&socket).await.is_ok() {
+                diagnostics.error(
+                    "api",
+                    format!("{} is already in use by another session", socket.display()),
+                );
+                return;
+            }
+            let _ = fs::remove_file(&socket);
+            let listener = match UnixListener::bind(&socket) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    diagnostics.error(
+                        "api",
+                        format!("failed to listen on {}: {}", socket.display(), e),
+                    );
+                    return;
+                }
+            };
+            listening.store(true, Ordering::Relaxed);
+            diagnostics.info("api", format!("listening on {}", socket.display()));
+            let mut client = 0;
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        client += 1;
+                        diagnostics.info("api", format!("client {} connected", client));
+                        tokio::spawn(serve(
+                            stream,
+                            client,
+                            tx.clone(),
+                            subscribers.clone(),
+                            diagnostics.clone(),
+                            wakeup.clone(),
+                        ));
+                    }
+                    Err(e) => diagnostics.warning("api", format!("accept returned error: {}", e)),
+                }
+            }
+        });
+        Api {
+            path: path.to_path_buf(),
+            bound,
+            rx,
+            events,
+            task,
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        while let Ok(request) = self.rx.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+
+    pub fn publish(&self, event: &str, data: Value) {
+        if self.events.receiver_count() > 0 {
+            let _ = self
+                .events
+                .send(json!({ "event": event, "data": data }).to_string());
+        }
+    }
+}
+
+impl Drop for Api {
+    fn drop(&mut self) {
+        self.task.abort();
+        if self.bound.load(Ordering::Relaxed) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+async fn serve(
+    stream: UnixStream,
+    client: u64,
+    tx: UnboundedSender<Request>,
+    events: broadcast::Sender<String>,
+    diagnostics: DiagnosticsSender,
+    wakeup: Arc<Notify>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscription: Option<broadcast::Receiver<String>> = None;
+    loop {
+        let response = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => handle(&line, client, &tx, &events, &mut subscription, &wakeup).await,
+                Ok(None) | Err(_) => break,
+            },
+            event = async { subscription.as_mut().unwrap().recv().await }, if subscription.is_some() => {
+                match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        json!({ "event": "lagged", "data": missed }).to_string()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        if writer
+            .write_all(format!("{}\n", response).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    diagnostics.info("api", format!("client {} disconnected", client));
+}
+
+async fn handle(
+    line: &str,
+    client: u64,
+    tx: &UnboundedSender<Request>,
+    events: &broadcast::Sender<String>,
+    subscription: &mut Option<broadcast::Receiver<String>>,
+    wakeup: &Notify,
+) -> String {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return json!({ "id": null, "error": format!("invalid JSON: {}", e) }).to_string()
+        }
+    };
+    let id = request["id"].clone();
+    let method = request["method"].as_str().unwrap_or_default().to_string();
+    let params = request["params"].clone();
+    let result = match method.as_str() {
+        "subscribe" => {
+            *subscription = Some(events.subscribe());
+            Ok(Value::Bool(true))
+        }
+        "unsubscribe" => {
+            *subscription = None;
+            Ok(Value::Bool(true))
+        }
+        _ => {
+            let (request, response) =
+                Request::new("api", format!("client {}", client), &method, params);
+            if tx.send(request).is_err() {
+                return json!({ "id": id, "error": "session closed" }).to_string();
+            }
+            wakeup.notify_one();
+            response.await.unwrap_or(Err("session closed".to_string()))
+        }
+    };
+    match result {
+        Ok(result) => json!({ "id": id, "result": result }).to_string(),
+        Err(error) => json!({ "id": id, "error": error }).to_string(),
+    }
+}
+
+fn param_str<'a>(params: &'a Value, name: &str) -> Result<&'a str, String> {
+    params[name]
+        .as_str()
+        .ok_or(format!("missing string parameter {}", name))
+}
+
+fn param_u16(params: &Value, name: &str) -> Result<u16, String> {
+    params[name]
+        .as_u64()
+        .filter(|value| *value <= 0x7FFF)
+        .map(|value| value as u16)
+        .ok_or(format!("missing or invalid parameter {}", name))
+}
+
+pub fn parse_call(method: &str, params: &Value) -> Result<Call, String> {
+    match method {
+        "get" => Ok(Call::Get(params["group"].as_str().map(str::to_string))),
+        "status" => Ok(Call::Status),
+        "write" => {
+            let group = param_str(params, "group")?;
+            let name = param_str(params, "name")?;
+            let value = param_u16(params, "value")?;
+            let info =
+                find_register(group, name).ok_or(format!("unknown register {} {}", group, name))?;
+            if !is_writable(&info) {
+                return Err(format!("{} {} is not writable", info.group, info.name));
+            }
+            match info.group {
+                "threshold" => Ok(Call::Action(Action::WriteThreshold(
+                    (info.address - THRESHOLDS_ADDRESS) as usize,
+                    value,
+                ))),
+                "dac" => Ok(Call::Action(Action::WriteDac(
+                    (info.address - DAC_ADDRESS) as usize,
+                    value,
+                ))),
+                "offset" => Ok(Call::Action(Action::WriteOffset(
+                    (info.address - OFFSETS_ADDRESS) as usize,
+                    value,
+                ))),
+                group => Err(format!("group {} is not writable", group)),
+            }
+        }
+        "control" => {
+            let name = param_str(params, "command")?;
+            CONTROL_COMMANDS
+                .iter()
+                .find(|(command, _)| *command == name)
+                .map(|(_, command)| Call::Action(Action::Control(*command)))
+                .ok_or(format!("unknown control command {}", name))
+        }
+        "protection_toggle" => {
+            let name = param_str(params, "name")?;
+            PROTECTION_NAMES
+                .iter()
+                .position(|protection| *protection == name)
+                .map(|i| Call::Action(Action::ToggleProtection(i)))
+                .ok_or(format!("unknown protection {}", name))
+        }
+        "power_toggle" => Ok(Call::Action(Action::TogglePowerEnable)),
+        "hard_reset" => Ok(Call::Action(Action::HardReset)),
+        "dac_clear" => Ok(Call::Action(Action::ClearDac)),
+        "tnr_launch" if params.is_null() => Ok(Call::TnrLaunch(None)),
+        "tnr_launch" => Ok(Call::TnrLaunch(Some([
+            param_u16(params, "period")?,
+            param_u16(params, "pulse_width")?,
+            param_u16(params, "count")?,
+        ]))),
+        "tnr_stop" => Ok(Call::Action(Action::TnrStop)),
+        "preset_load" => Ok(Call::LoadPreset(param_str(params, "name")?.to_string())),
+        method => Err(format!("unknown method {}", method)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::ControlCommand, diagnostics::Diagnostics, sspa::CHANNEL_NAMES};
+    use std::{env, process};
+
+    fn action(method: &str, params: Value) -> Result<Action, String> {
+        match parse_call(method, &params)? {
+            Call::Action(action) => Ok(action),
+            _ => Err(format!("{} is not an action", method)),
+        }
+    }
+
+    #[test]
+    fn parse_call_maps_writes_to_actions() {
+        let params = json!({ "group": "dac", "name": CHANNEL_NAMES[2], "value": 77 });
+        assert_eq!(action("write", params), Ok(Action::WriteDac(2, 77)));
+        let params = json!({ "command": "alarms_reset" });
+        assert_eq!(
+            action("control", params),
+            Ok(Action::Control(ControlCommand::AlarmsReset))
+        );
+        assert_eq!(action("hard_reset", Value::Null), Ok(Action::HardReset));
+    }
+
+    #[test]
+    fn parse_call_rejects_bad_params() {
+        let params = json!({ "group": "dac", "name": CHANNEL_NAMES[0], "value": 0x8000 });
+        assert_eq!(
+            action("write", params).unwrap_err(),
+            "missing or invalid parameter value"
+        );
+        let params = json!({ "group": "dac", "name": "gan_9", "value": 1 });
+        assert_eq!(
+            action("write", params).unwrap_err(),
+            "unknown register dac gan_9"
+        );
+        let params = json!({ "group": "adc", "name": CHANNEL_NAMES[0], "value": 1 });
+        assert!(action("write", params)
+            .unwrap_err()
+            .ends_with("is not writable"));
+        let params = json!({ "command": "self_destruct" });
+        assert_eq!(
+            action("control", params).unwrap_err(),
+            "unknown control command self_destruct"
+        );
+        assert_eq!(
+            action("reboot", Value::Null).unwrap_err(),
+            "unknown method reboot"
+        );
+    }
+
+    #[test]
+    fn parse_call_reads_tnr_values() {
+        assert!(matches!(
+            parse_call("tnr_launch", &Value::Null),
+            Ok(Call::TnrLaunch(None))
+        ));
+        let params = json!({ "period": 100, "pulse_width": 10, "count": 3 });
+        assert!(matches!(
+            parse_call("tnr_launch", &params),
+            Ok(Call::TnrLaunch(Some([100, 10, 3])))
+        ));
+        let params = json!({ "period": 100, "count": 3 });
+        assert!(parse_call("tnr_launch", &params).is_err());
+    }
+
+    #[tokio::test]
+    async fn subscribe_and_unsubscribe_are_answered_locally() {
+        let (tx, mut rx) = unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let wakeup = Notify::new();
+        let mut subscription = None;
+        let line = r#"{"id": 1, "method": "subscribe"}"#;
+        let response = handle(line, 1, &tx, &events, &mut subscription, &wakeup).await;
+        assert_eq!(response, r#"{"id":1,"result":true}"#);
+        assert_eq!(events.receiver_count(), 1);
+        events.send("event".to_string()).unwrap();
+        assert_eq!(
+            subscription.as_mut().unwrap().recv().await.unwrap(),
+            "event"
+        );
+        let line = r#"{"id": 2, "method": "unsubscribe"}"#;
+        let response = handle(line, 1, &tx, &events, &mut subscription, &wakeup).await;
+        assert_eq!(response, r#"{"id":2,"result":true}"#);
+        assert!(subscription.is_none());
+        assert_eq!(events.receiver_count(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn dropping_an_unbound_session_leaves_the_socket() {
+        let path = env::temp_dir().join(format!("sspa_tui_{}_api.sock", process::id()));
+        let diagnostics = Diagnostics::new(16, None, Arc::new(Notify::new()));
+        let first = Api::new(&path, diagnostics.sender(), Arc::new(Notify::new()));
+        while !first.bound.load(Ordering::Relaxed) {
+            tokio::task::yield_now().await;
+        }
+        let second = Api::new(&path, diagnostics.sender(), Arc::new(Notify::new()));
+        while !second.task.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        drop(second);
+        assert!(path.exists());
+        drop(first);
+        assert!(!path.exists());
+    }
+}
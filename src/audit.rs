@@ -0,0 +1,313 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{config_dir, host_name, user_name, Config},
+    diagnostics::DiagnosticsSender,
+    scrollback::{Line, Scrollback, Stream},
+};
+
+const CSV_HEADER: &str = "time,user,host,target,serial_number,operation,old,new,outcome,origin";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: String,
+    pub user: String,
+    pub host: String,
+    pub target: String,
+    pub serial_number: Option<u16>,
+    pub operation: String,
+    pub old: String,
+    pub new: String,
+    pub outcome: String,
+    pub origin: String,
+}
+
+impl AuditEntry {
+    fn failed(&self) -> bool {
+        !matches!(
+            self.outcome.as_str(),
+            "sent" | "started" | "verified" | "ok"
+        )
+    }
+
+    fn csv(&self) -> String {
+        let serial_number = self.serial_number.map(|serial| serial.to_string());
+        [
+            &self.time,
+            &self.user,
+            &self.host,
+            &self.target,
+            serial_number.as_deref().unwrap_or(""),
+            &self.operation,
+            &self.old,
+            &self.new,
+            &self.outcome,
+            &self.origin,
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let serial_number = match self.serial_number {
+            Some(serial) => serial.to_string(),
+            None => "?".to_string(),
+        };
+        write!(
+            f,
+            "{} {}@{} {} unit {} {}: {} -> {} {} ({})",
+            self.time,
+            self.user,
+            self.host,
+            self.target,
+            serial_number,
+            self.operation,
+            self.old,
+            self.new,
+            self.outcome,
+            self.origin
+        )
+    }
+}
+
+pub struct Audit {
+    path: PathBuf,
+    file: Option<File>,
+    user: String,
+    host: String,
+    target: String,
+    diagnostics: DiagnosticsSender,
+    scrollback: Scrollback,
+    started: Instant,
+    seq: u64,
+}
+
+impl Audit {
+    pub fn new(config: &Config, diagnostics: DiagnosticsSender) -> Audit {
+        let path = config
+            .audit_file
+            .clone()
+            .or_else(|| config_dir().map(|dir| dir.join("audit.log")))
+            .unwrap_or_else(|| PathBuf::from("audit.log"));
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = fs::create_dir_all(dir);
+        }
+        let mut audit = Audit {
+            file: None,
+            user: user_name(),
+            host: host_name(),
+            target: target_host(&config.ssh_command),
+            diagnostics,
+            scrollback: Scrollback::new(config.scrollback),
+            started: Instant::now(),
+            seq: 0,
+            path,
+        };
+        for entry in audit.entries().unwrap_or_default() {
+            audit.push(&entry, Duration::ZERO);
+        }
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&audit.path)
+        {
+            Ok(file) => audit.file = Some(file),
+            Err(e) => audit.diagnostics.error(
+                "audit",
+                format!("failed to open {}: {}", audit.path.display(), e),
+            ),
+        }
+        audit
+    }
+
+    pub fn scrollback_mut(&mut self) -> &mut Scrollback {
+        &mut self.scrollback
+    }
+
+    pub fn entry(&self, operation: impl Into<String>, old: String, new: String) -> AuditEntry {
+        AuditEntry {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            user: self.user.clone(),
+            host: self.host.clone(),
+            target: self.target.clone(),
+            serial_number: None,
+            operation: operation.into(),
+            old,
+            new,
+            outcome: String::new(),
+            origin: "ui".to_string(),
+        }
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) {
+        let json = serde_json::to_string(&entry).expect("ERROR: audit entry is not serializable");
+        let written = match &mut self.file {
+            Some(file) => writeln!(file, "{}", json).and_then(|_| file.flush()),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        };
+        if let Err(e) = written {
+            self.diagnostics.error(
+                "audit",
+                format!("failed to append to {}: {}", self.path.display(), e),
+            );
+        }
+        self.push(&entry, self.started.elapsed());
+    }
+
+    fn push(&mut self, entry: &AuditEntry, timestamp: Duration) {
+        let text = entry.to_string();
+        self.scrollback.push(Line {
+            stream: match entry.failed() {
+                true => Stream::Stderr,
+                false => Stream::Stdout,
+            },
+            timestamp,
+            seq: self.seq,
+            raw: text.clone().into_bytes(),
+            text,
+            partial: false,
+        });
+        self.seq += 1;
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>, String> {
+        let text = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    pub fn export(&self, path: &Path) -> Result<usize, String> {
+        let entries = self.entries()?;
+        let mut file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        writeln!(file, "{}", CSV_HEADER).map_err(|e| e.to_string())?;
+        for entry in &entries {
+            writeln!(file, "{}", entry.csv()).map_err(|e| e.to_string())?;
+        }
+        file.flush().map_err(|e| e.to_string())?;
+        Ok(entries.len())
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn target_host(ssh_command: &str) -> String {
+    let mut words = ssh_command.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    if let Some(destination) = ssh_command
+        .split_whitespace()
+        .find(|word| word.contains('@'))
+    {
+        return destination.to_string();
+    }
+    match program.ends_with("ssh") {
+        true => words
+            .find(|word| !word.starts_with('-'))
+            .unwrap_or(program)
+            .to_string(),
+        false => program.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use std::{env, process, sync::Arc};
+    use tokio::sync::Notify;
+
+    #[test]
+    fn quotes_fields_with_separators() {
+        assert_eq!(
+            csv_field("write dac.gan_1_current"),
+            "write dac.gan_1_current"
+        );
+        assert_eq!(
+            csv_field("+store_nvm,-alarms_reset"),
+            "\"+store_nvm,-alarms_reset\""
+        );
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\rlines"), "\"two\rlines\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn finds_the_target_host() {
+        assert_eq!(target_host("ssh -p 2222 root@sspa-7"), "root@sspa-7");
+        assert_eq!(target_host("ssh sspa-7 cat /dev/ttyS1"), "sspa-7");
+        assert_eq!(target_host("/tmp/fake.sh"), "/tmp/fake.sh");
+    }
+
+    #[test]
+    fn exports_recorded_entries_as_csv() {
+        let dir = env::temp_dir();
+        let log = dir.join(format!("sspa_tui_{}_audit.log", process::id()));
+        let csv = dir.join(format!("sspa_tui_{}_audit.csv", process::id()));
+        let _ = fs::remove_file(&log);
+        let config = Config {
+            audit_file: Some(log.clone()),
+            ssh_command: "ssh root@sspa-7".to_string(),
+            ..Config::default()
+        };
+        let diagnostics = Diagnostics::new(16, None, Arc::new(Notify::new()));
+        let mut audit = Audit::new(&config, diagnostics.sender());
+        let mut entry = audit.entry("write control +store_nvm", "0x0000".into(), "0x8001".into());
+        entry.outcome = "not confirmed".to_string();
+        entry.origin = "script \"bench, final\".rhai".to_string();
+        audit.record(entry);
+        assert_eq!(audit.export(&csv).unwrap(), 1);
+        let text = fs::read_to_string(&csv).unwrap();
+        fs::remove_file(&log).unwrap();
+        fs::remove_file(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].ends_with(
+            ",root@sspa-7,,write control +store_nvm,0x0000,0x8001,not confirmed,\
+             \"script \"\"bench, final\"\".rhai\""
+        ));
+    }
+
+    #[test]
+    fn only_confirmed_outcomes_count_as_success() {
+        let failed = |outcome: &str| {
+            AuditEntry {
+                time: String::new(),
+                user: String::new(),
+                host: String::new(),
+                target: String::new(),
+                serial_number: None,
+                operation: "flash".to_string(),
+                old: "-".to_string(),
+                new: "-".to_string(),
+                outcome: outcome.to_string(),
+                origin: "ui".to_string(),
+            }
+            .failed()
+        };
+        assert!(!failed("verified"));
+        assert!(!failed("ok"));
+        assert!(failed("not confirmed"));
+        assert!(failed("read-only mode"));
+        assert!(failed("failed, exit status: 1"));
+    }
+}
@@ -0,0 +1,403 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::config_dir,
+    script::Bridge,
+    snapshot::DiffRow,
+    sspa::{
+        find_register, Register, RegisterState, CHANNEL_NAMES, CONTROL_ADDRESS, PROTECTION_NAMES,
+        SERIAL_NUMBER_ADDRESS, THRESHOLD_NAMES,
+    },
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(3);
+const VERIFY_RETRY: i64 = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    pub taken: String,
+    pub serial_number: Option<u16>,
+    pub threshold: BTreeMap<String, u16>,
+    pub offset: BTreeMap<String, u16>,
+    pub dac: BTreeMap<String, u16>,
+    pub protection_disabled: BTreeMap<String, bool>,
+}
+
+#[derive(Clone)]
+struct Setting {
+    group: &'static str,
+    name: &'static str,
+    value: u16,
+}
+
+impl Setting {
+    fn field(&self) -> String {
+        format!("{}.{}", self.group, self.name)
+    }
+
+    fn protection(&self) -> Option<usize> {
+        PROTECTION_NAMES
+            .iter()
+            .position(|name| *name == self.name)
+            .filter(|_| self.group == "protection_disabled")
+    }
+
+    fn current(&self, registers: &BTreeMap<u16, Register>) -> Option<u16> {
+        let (address, bit) = match self.protection() {
+            Some(i) => (CONTROL_ADDRESS, Some(4 - i)),
+            None => (find_register(self.group, self.name)?.address, None),
+        };
+        let reg = registers
+            .get(&address)
+            .filter(|reg| reg.state() != RegisterState::ParityError)?;
+        Some(match bit {
+            Some(bit) => (reg.value() >> bit) & 1,
+            None => reg.value(),
+        })
+    }
+
+    fn read(&self, bridge: &Bridge, timeout: Duration) -> Result<u16, String> {
+        match self.protection() {
+            Some(i) => {
+                let control = bridge.read_retry("control", "control", timeout)?;
+                Ok(((control >> (4 - i)) & 1) as u16)
+            }
+            None => Ok(bridge.read_retry(self.group, self.name, timeout)? as u16),
+        }
+    }
+
+    fn write(&self, bridge: &Bridge) -> Result<(), String> {
+        match self.protection() {
+            Some(_) => bridge
+                .call("protection_toggle", json!({ "name": self.name }))
+                .map(|_| ()),
+            None => bridge.write(self.group, self.name, self.value as i64),
+        }
+    }
+
+    fn verify(&self, bridge: &Bridge) -> Result<(), String> {
+        let deadline = Instant::now() + VERIFY_TIMEOUT;
+        loop {
+            let value = self.read(bridge, VERIFY_TIMEOUT)?;
+            if value == self.value {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "{} read back {}, expected {}",
+                    self.field(),
+                    value,
+                    self.value
+                ));
+            }
+            bridge.sleep(VERIFY_RETRY)?;
+        }
+    }
+}
+
+fn describe(setting: &Setting, value: Option<u16>) -> String {
+    match (setting.protection(), value) {
+        (_, None) => "-".to_string(),
+        (Some(_), Some(value)) => (value == 1).to_string(),
+        (None, Some(value)) => value.to_string(),
+    }
+}
+
+impl Backup {
+    pub fn new(registers: &BTreeMap<u16, Register>) -> Result<Backup, String> {
+        let mut backup = Backup {
+            taken: Local::now().to_rfc3339(),
+            serial_number: registers.get(&SERIAL_NUMBER_ADDRESS).map(Register::value),
+            threshold: BTreeMap::new(),
+            offset: BTreeMap::new(),
+            dac: BTreeMap::new(),
+            protection_disabled: BTreeMap::new(),
+        };
+        for setting in layout() {
+            let value = setting
+                .current(registers)
+                .ok_or(format!("no valid telemetry for {}", setting.field()))?;
+            let name = setting.name.to_string();
+            match setting.group {
+                "threshold" => backup.threshold.insert(name, value),
+                "offset" => backup.offset.insert(name, value),
+                "dac" => backup.dac.insert(name, value),
+                _ => backup
+                    .protection_disabled
+                    .insert(name, value == 1)
+                    .map(u16::from),
+            };
+        }
+        Ok(backup)
+    }
+
+    pub fn load(path: &Path) -> Result<Backup, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let backup: Backup = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let known = layout();
+        let names = [
+            ("threshold", backup.threshold.keys().collect::<Vec<_>>()),
+            ("offset", backup.offset.keys().collect()),
+            ("dac", backup.dac.keys().collect()),
+            (
+                "protection_disabled",
+                backup.protection_disabled.keys().collect(),
+            ),
+        ];
+        for (group, names) in names {
+            for name in names {
+                if !known.iter().any(|s| s.group == group && s.name == name) {
+                    return Err(format!("unknown setting {}.{}", group, name));
+                }
+            }
+        }
+        if backup.settings().is_empty() {
+            return Err("backup has no settings".to_string());
+        }
+        Ok(backup)
+    }
+
+    pub fn save(&self, path: Option<PathBuf>) -> Result<PathBuf, String> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let dir = config_dir()
+                    .map(|dir| dir.join("backups"))
+                    .unwrap_or_else(|| PathBuf::from("."));
+                fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+                let serial = match self.serial_number {
+                    Some(serial) => serial.to_string(),
+                    None => "unknown".to_string(),
+                };
+                let taken = Local::now().format("%Y%m%d-%H%M%S");
+                dir.join(format!("{}-{}.json", serial, taken))
+            }
+        };
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    fn settings(&self) -> Vec<Setting> {
+        layout()
+            .into_iter()
+            .filter_map(|mut setting| {
+                setting.value = match setting.group {
+                    "threshold" => *self.threshold.get(setting.name)?,
+                    "offset" => *self.offset.get(setting.name)?,
+                    "dac" => *self.dac.get(setting.name)?,
+                    _ => *self.protection_disabled.get(setting.name)? as u16,
+                };
+                Some(setting)
+            })
+            .collect()
+    }
+
+    pub fn preview(&self, registers: &BTreeMap<u16, Register>) -> Vec<DiffRow> {
+        self.settings()
+            .iter()
+            .map(|setting| DiffRow {
+                field: setting.field(),
+                old: describe(setting, setting.current(registers)),
+                new: describe(setting, Some(setting.value)),
+            })
+            .collect()
+    }
+
+    pub fn restore(self, bridge: &Bridge, write: bool, store: bool) -> Result<(), String> {
+        let settings = self.settings();
+        let mut changes = Vec::new();
+        for setting in &settings {
+            let current = setting.read(bridge, READ_TIMEOUT)?;
+            if current != setting.value {
+                bridge.log(&format!(
+                    "{}: {} -> {}",
+                    setting.field(),
+                    describe(setting, Some(current)),
+                    describe(setting, Some(setting.value))
+                ));
+                changes.push(setting.clone());
+            }
+        }
+        bridge.log(&format!(
+            "{} of {} settings differ",
+            changes.len(),
+            settings.len()
+        ));
+        if !write {
+            return Ok(());
+        }
+        for setting in safe_order(changes) {
+            setting.write(bridge)?;
+            setting.verify(bridge)?;
+            bridge.log(&format!("{} verified", setting.field()));
+        }
+        if store {
+            bridge.call("control", json!({ "command": "store_nvm" }))?;
+            bridge.log("stored to non volatile memory");
+        }
+        Ok(())
+    }
+}
+
+fn layout() -> Vec<Setting> {
+    let setting = |group, name| Setting {
+        group,
+        name,
+        value: 0,
+    };
+    let mut settings: Vec<Setting> = PROTECTION_NAMES
+        .iter()
+        .map(|name| setting("protection_disabled", *name))
+        .collect();
+    // the serial number belongs to the unit, never copy it
+    settings.extend(
+        THRESHOLD_NAMES[..9]
+            .iter()
+            .map(|name| setting("threshold", *name)),
+    );
+    settings.extend(CHANNEL_NAMES.iter().map(|name| setting("offset", *name)));
+    settings.extend(CHANNEL_NAMES.iter().map(|name| setting("dac", *name)));
+    settings
+}
+
+// protections come back on before anything moves and only go off once the
+// thresholds, offsets and bias are where the backup wants them
+fn safe_order(changes: Vec<Setting>) -> Vec<Setting> {
+    let (protections, registers): (Vec<Setting>, Vec<Setting>) = changes
+        .into_iter()
+        .partition(|setting| setting.protection().is_some());
+    let (disable, enable): (Vec<Setting>, Vec<Setting>) = protections
+        .into_iter()
+        .partition(|setting| setting.value == 1);
+    enable.into_iter().chain(registers).chain(disable).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn change(group: &'static str, name: &'static str, value: u16) -> Setting {
+        Setting { group, name, value }
+    }
+
+    fn write_file(name: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("sspa_tui_{}_{}.json", process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn registers() -> BTreeMap<u16, Register> {
+        let mut registers: BTreeMap<u16, Register> = layout()
+            .iter()
+            .filter_map(|setting| find_register(setting.group, setting.name))
+            .map(|info| (info.address, Register::new(Register::encode(3))))
+            .collect();
+        // bit 4 disables the first protection
+        registers.insert(CONTROL_ADDRESS, Register::new(Register::encode(1 << 4)));
+        registers
+    }
+
+    #[test]
+    fn safe_order_enables_protections_first_and_disables_them_last() {
+        let changes = vec![
+            change("dac", "gan_1_current", 10),
+            change("protection_disabled", "reflected_power", 1),
+            change("threshold", "over_drive", 20),
+            change("protection_disabled", "over_drive", 0),
+            change("offset", "drive_level", 30),
+        ];
+        let order: Vec<String> = safe_order(changes)
+            .iter()
+            .map(|setting| format!("{}={}", setting.field(), setting.value))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "protection_disabled.over_drive=0",
+                "dac.gan_1_current=10",
+                "threshold.over_drive=20",
+                "offset.drive_level=30",
+                "protection_disabled.reflected_power=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn new_reads_every_setting_except_the_serial_number() {
+        let backup = Backup::new(&registers()).unwrap();
+        assert_eq!(backup.threshold.len(), 9);
+        assert!(!backup.threshold.contains_key("serial_number"));
+        assert_eq!(backup.dac["gan_1_current"], 3);
+        assert!(backup.protection_disabled[PROTECTION_NAMES[0]]);
+        assert!(!backup.protection_disabled[PROTECTION_NAMES[1]]);
+    }
+
+    #[test]
+    fn new_refuses_missing_or_corrupted_telemetry() {
+        let mut registers = registers();
+        let dac = find_register("dac", "gan_1_current").unwrap().address;
+        registers.insert(dac, Register::new(Register::encode(3) ^ 0x0001));
+        assert_eq!(
+            Backup::new(&registers).err().unwrap(),
+            "no valid telemetry for dac.gan_1_current"
+        );
+        registers.remove(&dac);
+        assert!(Backup::new(&registers).is_err());
+    }
+
+    #[test]
+    fn load_round_trips_a_saved_backup() {
+        let path = write_file("round_trip", "");
+        Backup::new(&registers())
+            .unwrap()
+            .save(Some(path.clone()))
+            .unwrap();
+        let backup = Backup::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(backup.settings().len(), layout().len());
+        assert!(backup
+            .preview(&registers())
+            .iter()
+            .all(|row| !row.changed()));
+    }
+
+    #[test]
+    fn load_rejects_unknown_and_empty_backups() {
+        let empty = r#"{"taken": "", "serial_number": null, "threshold": {}, "offset": {},
+            "dac": {}, "protection_disabled": {}}"#;
+        let cases = [
+            ("empty", empty.to_string(), "backup has no settings"),
+            (
+                "serial",
+                empty.replace(r#""threshold": {}"#, r#""threshold": {"serial_number": 7}"#),
+                "unknown setting threshold.serial_number",
+            ),
+            (
+                "unknown",
+                empty.replace(r#""dac": {}"#, r#""dac": {"spare": 7}"#),
+                "unknown setting dac.spare",
+            ),
+        ];
+        for (name, text, error) in cases {
+            let path = write_file(name, &text);
+            let result = Backup::load(&path);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(result.err().as_deref(), Some(error));
+        }
+        let path = write_file("truncated", &empty[..20]);
+        let result = Backup::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
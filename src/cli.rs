@@ -0,0 +1,681 @@
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep, sleep_until, Instant},
+};
+
+use crate::{
+    audit::{Audit, AuditEntry},
+    backup::Backup,
+    config::{user_name, Config},
+    connection::ConnectionState,
+    device::{self, DeviceCommand},
+    diagnostics::Diagnostics,
+    launcher::Launcher,
+    snapshot::{self, Snapshot},
+    sspa::{
+        bits, find_register, is_writable, register_map, Register, RegisterInfo, RegisterState,
+        SSPAState, CONTROL_ADDRESS, CONTROL_BITS, STATE_ADDRESS, STATUS_ADDRESS, STATUS_BITS,
+    },
+    state::{describe_command, unit_entry, StateKeeper},
+    telemetry,
+};
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_USAGE: i32 = 1;
+pub const EXIT_CONNECTION: i32 = 2;
+pub const EXIT_PARITY: i32 = 3;
+pub const EXIT_NOT_CONFIRMED: i32 = 4;
+pub const EXIT_SCRIPT_FAILED: i32 = 5;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const USAGE: &str = "usage:
+    sspa_tui [--read-only]                    start the interface, --read-only refuses every
+                                              write to the unit and the firmware
+    sspa_tui get <group> [--json]             print decoded registers
+    sspa_tui set <group> <name> <value>       write a register and wait for readback
+    sspa_tui run <script.rhai>                run a script against the unit
+    sspa_tui test <plan.toml> [--yes]         run a test plan, write its report and ask to
+                                              sign off the verdict, --yes signs it off
+    sspa_tui snapshot [file]                  save every register to a snapshot file
+    sspa_tui diff <old> [new]                 compare two snapshots, or one against the unit
+    sspa_tui backup [file]                    save thresholds, DAC, offsets and protections
+    sspa_tui restore <file> [--yes] [--store] preview a backup against the unit, --yes writes
+                                              and verifies it, --store saves it to NVM
+
+groups: all, status, state, version, control, adc, threshold, dac, offset
+options: --json, --timeout <seconds>, --yes, --store, --read-only
+
+exit codes: 0 ok, 1 usage, invalid configuration or local file error,
+            2 connection failed, timed out or incomplete telemetry,
+            3 parity error in a read register, 4 write not confirmed,
+            5 script, test plan or restore failed";
+
+enum Request {
+    Get(String),
+    Set { address: u16, value: u16 },
+    Run(PathBuf),
+    Test(PathBuf),
+    Snapshot(Option<PathBuf>),
+    Diff(PathBuf, Option<PathBuf>),
+    Backup(Option<PathBuf>),
+    Restore(PathBuf),
+}
+
+pub struct Cli {
+    request: Request,
+    json: bool,
+    yes: bool,
+    store: bool,
+    timeout: Duration,
+}
+
+pub struct Args {
+    pub read_only: bool,
+    pub command: Option<Cli>,
+}
+
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut read_only = false;
+    let mut json = false;
+    let mut yes = false;
+    let mut store = false;
+    let mut timeout = DEFAULT_TIMEOUT;
+    let mut words = Vec::new();
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--yes" => yes = true,
+            "--store" => store = true,
+            "--read-only" => read_only = true,
+            "--timeout" => {
+                let seconds: f64 = options
+                    .next()
+                    .and_then(|seconds| seconds.parse().ok())
+                    .filter(|seconds: &f64| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or("--timeout needs a positive number of seconds")?;
+                timeout = Duration::from_secs_f64(seconds);
+            }
+            "-h" | "--help" | "help" => return Err(String::new()),
+            arg if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => words.push(arg.as_str()),
+        }
+    }
+    let request = match words.as_slice() {
+        [] if args.iter().all(|arg| arg == "--read-only") => {
+            return Ok(Args {
+                read_only,
+                command: None,
+            })
+        }
+        ["get", group] => {
+            let group = group_name(group);
+            if group != "all" && !register_map().iter().any(|info| info.group == group) {
+                return Err(format!("unknown group {}", group));
+            }
+            Request::Get(group.to_string())
+        }
+        ["set", group, name, value] => {
+            let group = group_name(group);
+            if !["threshold", "dac", "offset"].contains(&group) {
+                return Err(format!("group {} is not writable", group));
+            }
+            let info =
+                find_register(group, name).ok_or(format!("unknown register {} {}", group, name))?;
+            if !is_writable(&info) {
+                return Err(format!("{} {} is not writable", group, info.name));
+            }
+            let address = info.address;
+            let value = parse_value(value)
+                .filter(|value| *value <= 0x7FFF)
+                .ok_or(format!("invalid value {}", value))?;
+            Request::Set { address, value }
+        }
+        ["run", path] => Request::Run(PathBuf::from(path)),
+        ["test", path] => Request::Test(PathBuf::from(path)),
+        ["snapshot"] => Request::Snapshot(None),
+        ["snapshot", path] => Request::Snapshot(Some(PathBuf::from(path))),
+        ["diff", old] => Request::Diff(PathBuf::from(old), None),
+        ["diff", old, new] => Request::Diff(PathBuf::from(old), Some(PathBuf::from(new))),
+        ["backup"] => Request::Backup(None),
+        ["backup", path] => Request::Backup(Some(PathBuf::from(path))),
+        ["restore", path] => Request::Restore(PathBuf::from(path)),
+        _ => return Err("invalid command".to_string()),
+    };
+    if store && !yes {
+        return Err("--store needs --yes".to_string());
+    }
+    Ok(Args {
+        read_only,
+        command: Some(Cli {
+            request,
+            json,
+            yes,
+            store,
+            timeout,
+        }),
+    })
+}
+
+fn group_name(group: &str) -> &str {
+    match group {
+        "thresholds" => "threshold",
+        "offsets" => "offset",
+        group => group,
+    }
+}
+
+fn parse_value(value: &str) -> Option<u16> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+pub async fn run(cli: Cli, config: &Config) -> i32 {
+    match &cli.request {
+        Request::Run(path) | Request::Test(path) | Request::Restore(path) => {
+            return run_script(&cli, path, config).await
+        }
+        Request::Diff(old, Some(new)) => {
+            return match (Snapshot::load(old), Snapshot::load(new)) {
+                (Ok(old_snapshot), Ok(new_snapshot)) => {
+                    print_diff(old, new, &old_snapshot, &new_snapshot)
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    eprintln!("ERROR: failed to read snapshot: {}", e);
+                    EXIT_USAGE
+                }
+            }
+        }
+        Request::Diff(_, None)
+        | Request::Get(_)
+        | Request::Set { .. }
+        | Request::Snapshot(_)
+        | Request::Backup(_) => {}
+    }
+    let baseline = match &cli.request {
+        Request::Diff(old, None) => match Snapshot::load(old) {
+            Ok(baseline) => Some(baseline),
+            Err(e) => {
+                eprintln!("ERROR: failed to read {}: {}", old.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        _ => None,
+    };
+    let wakeup = Arc::new(Notify::new());
+    let mut diagnostics = Diagnostics::new(
+        config.scrollback,
+        config.diagnostics_file.as_deref(),
+        wakeup.clone(),
+    );
+    let mut audit = match cli.request {
+        Request::Set { address, value } => {
+            let mut audit = Audit::new(config, diagnostics.sender());
+            let entry = set_entry(&audit, &BTreeMap::new(), address, value);
+            if let Err(e) = device::guard(config.read_only, &mut audit, &entry) {
+                eprintln!("ERROR: {}, refusing to write to the unit", e);
+                return EXIT_USAGE;
+            }
+            Some(audit)
+        }
+        _ => None,
+    };
+    let mut ssh = Launcher::new(
+        "ssh",
+        config.scrollback,
+        diagnostics.sender(),
+        wakeup.clone(),
+    );
+    ssh.launch(&config.ssh_command);
+
+    let wanted: Vec<RegisterInfo> = match &cli.request {
+        Request::Get(group) => register_map()
+            .into_iter()
+            .filter(|info| group == "all" || info.group == group)
+            .collect(),
+        Request::Snapshot(_) | Request::Diff(..) => register_map(),
+        Request::Backup(_) => register_map()
+            .into_iter()
+            .filter(|info| ["threshold", "offset", "dac", "control"].contains(&info.group))
+            .collect(),
+        Request::Set { address, .. } => register_map()
+            .into_iter()
+            .filter(|info| info.address == *address)
+            .collect(),
+        Request::Run(_) | Request::Test(_) | Request::Restore(_) => Vec::new(),
+    };
+    let deadline = Instant::now() + cli.timeout;
+    let mut registers: BTreeMap<u16, Register> = BTreeMap::new();
+    let mut connected = false;
+    let mut written = false;
+    let mut entry = None;
+
+    let code = loop {
+        for line in ssh.poll().iter().filter(|line| !line.partial) {
+            connected = true;
+            if let Some((address, raw)) = telemetry::parse_line(&line.text) {
+                registers.insert(address, Register::new(raw));
+            }
+        }
+        diagnostics.poll();
+        match cli.request {
+            Request::Get(_) | Request::Snapshot(_) | Request::Diff(..) | Request::Backup(_) => {
+                if wanted
+                    .iter()
+                    .all(|info| registers.contains_key(&info.address))
+                {
+                    break EXIT_OK;
+                }
+            }
+            Request::Run(_) | Request::Test(_) | Request::Restore(_) => {}
+            Request::Set { address, value } => {
+                if !written && connected {
+                    let command = DeviceCommand::Write { address, value };
+                    if !ssh.send(&command.to_line()) {
+                        break EXIT_CONNECTION;
+                    }
+                    written = true;
+                    if let Some(audit) = &audit {
+                        entry = Some(set_entry(audit, &registers, address, value));
+                    }
+                    registers.remove(&address);
+                }
+                let confirmed = registers
+                    .get(&address)
+                    .filter(|_| written)
+                    .is_some_and(|reg| {
+                        reg.state() != RegisterState::ParityError && reg.value() == value
+                    });
+                if confirmed {
+                    break EXIT_OK;
+                }
+            }
+        }
+        if !ssh.is_running() {
+            eprintln!("ERROR: connection closed");
+            break EXIT_CONNECTION;
+        }
+        tokio::select! {
+            _ = wakeup.notified() => {}
+            _ = sleep(POLL_INTERVAL) => {}
+            _ = sleep_until(deadline) => {
+                if let (Request::Set { address, value }, true) = (&cli.request, written) {
+                    match registers.get(address) {
+                        Some(reg) => eprintln!(
+                            "ERROR: read back 0x{:04x} from 0x{:02x}, expected 0x{:04x}",
+                            reg.value(),
+                            address,
+                            value
+                        ),
+                        None => eprintln!("ERROR: timed out waiting for readback"),
+                    }
+                    break EXIT_NOT_CONFIRMED;
+                }
+                let missing: Vec<String> = wanted
+                    .iter()
+                    .filter(|info| !registers.contains_key(&info.address))
+                    .map(|info| format!("{}.{}", info.group, info.name))
+                    .collect();
+                match connected {
+                    true => eprintln!("ERROR: timed out waiting for {}", missing.join(", ")),
+                    false => eprintln!("ERROR: timed out waiting for telemetry"),
+                }
+                break EXIT_CONNECTION;
+            }
+        }
+    };
+    ssh.stop();
+    if let (Request::Set { address, value }, Some(audit)) = (&cli.request, &mut audit) {
+        let mut entry = entry.unwrap_or_else(|| set_entry(audit, &registers, *address, *value));
+        entry.outcome = match (code, written) {
+            (EXIT_OK, _) => "verified",
+            (EXIT_NOT_CONFIRMED, _) => "not confirmed",
+            (_, true) => "connection lost",
+            (_, false) => "not connected",
+        }
+        .to_string();
+        audit.record(entry);
+    }
+    diagnostics.poll();
+
+    if code == EXIT_CONNECTION {
+        for (_, line) in diagnostics.scrollback_mut().lines() {
+            eprintln!("{}", line.text);
+        }
+        return code;
+    }
+    match &cli.request {
+        Request::Snapshot(path) => {
+            return match Snapshot::new(&registers, None).save(path.clone()) {
+                Ok(path) => {
+                    println!("{}", path.display());
+                    EXIT_OK
+                }
+                Err(e) => {
+                    eprintln!("ERROR: failed to save snapshot: {}", e);
+                    EXIT_USAGE
+                }
+            }
+        }
+        Request::Backup(path) => {
+            let backup = match Backup::new(&registers) {
+                Ok(backup) => backup,
+                Err(e) => {
+                    eprintln!("ERROR: incomplete telemetry: {}", e);
+                    return EXIT_CONNECTION;
+                }
+            };
+            return match backup.save(path.clone()) {
+                Ok(path) => {
+                    println!("{}", path.display());
+                    EXIT_OK
+                }
+                Err(e) => {
+                    eprintln!("ERROR: failed to save backup: {}", e);
+                    EXIT_USAGE
+                }
+            };
+        }
+        Request::Diff(old, None) => {
+            if let Some(baseline) = &baseline {
+                let live = Snapshot::new(&registers, None);
+                return print_diff(old, Path::new("live"), baseline, &live);
+            }
+        }
+        _ => {}
+    }
+    let registers: Vec<(&RegisterInfo, Register)> = wanted
+        .iter()
+        .filter_map(|info| Some((info, *registers.get(&info.address)?)))
+        .collect();
+    if cli.json {
+        let values: Vec<Value> = registers
+            .iter()
+            .map(|(info, reg)| register_json(info, reg))
+            .collect();
+        println!("{}", Value::Array(values));
+    } else {
+        print_table(&registers);
+    }
+    if code == EXIT_OK
+        && registers
+            .iter()
+            .any(|(_, reg)| reg.state() == RegisterState::ParityError)
+    {
+        return EXIT_PARITY;
+    }
+    code
+}
+
+async fn run_script(cli: &Cli, path: &Path, config: &Config) -> i32 {
+    let mut state = StateKeeper::headless(config);
+    let wakeup = state.wakeup();
+    let deadline = Instant::now() + cli.timeout;
+    while state.connection_state() != ConnectionState::Connected {
+        if Instant::now() >= deadline {
+            eprintln!("ERROR: timed out waiting for the connection");
+            return EXIT_CONNECTION;
+        }
+        tokio::select! {
+            _ = wakeup.notified() => {}
+            _ = sleep(POLL_INTERVAL) => {}
+        }
+        state.poll();
+    }
+    match cli.request {
+        Request::Test(_) => state.run_plan(path, true),
+        Request::Restore(_) => state.run_restore(path, cli.yes, cli.store),
+        _ => state.run_script(path, true),
+    }
+    loop {
+        // the last writes are audited once the unit reads them back or they time out
+        if let Some(result) = state.script_result().filter(|_| !state.writes_pending()) {
+            let code = match result {
+                Ok(()) => EXIT_OK,
+                Err(e) => {
+                    eprintln!("ERROR: {}", e);
+                    EXIT_SCRIPT_FAILED
+                }
+            };
+            sign_off(&mut state, cli.yes);
+            return code;
+        }
+        tokio::select! {
+            _ = wakeup.notified() => {}
+            _ = sleep(POLL_INTERVAL) => {}
+        }
+        state.poll();
+    }
+}
+
+fn set_entry(
+    audit: &Audit,
+    registers: &BTreeMap<u16, Register>,
+    address: u16,
+    value: u16,
+) -> AuditEntry {
+    let command = DeviceCommand::Write { address, value };
+    let (operation, old, new) = describe_command(command, registers);
+    let mut entry = unit_entry(audit, registers, &operation, old, new);
+    entry.origin = "cli".to_string();
+    entry
+}
+
+// the operator confirms the verdict of a test run, --yes confirms it up front
+fn sign_off(state: &mut StateKeeper, yes: bool) {
+    let verdict = match state.sign_off() {
+        Some(report) => report.verdict().to_string(),
+        None => return,
+    };
+    let confirmed = yes || {
+        eprint!("sign off verdict {} as {}? [y/N] ", verdict, user_name());
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+    };
+    if !confirmed {
+        state.leave_unsigned();
+        eprintln!("report left unsigned");
+        return;
+    }
+    match state.sign_report() {
+        Ok(path) => println!("report signed off in {}", path.display()),
+        Err(e) => eprintln!("ERROR: failed to sign report: {}", e),
+    }
+}
+
+fn print_diff(old_path: &Path, new_path: &Path, old: &Snapshot, new: &Snapshot) -> i32 {
+    let rows = snapshot::diff(old, new);
+    let width = rows
+        .iter()
+        .map(|row| row.old.len())
+        .max()
+        .unwrap_or(0)
+        .max(12);
+    let field = rows.iter().map(|row| row.field.len()).max().unwrap_or(0) + 2;
+    println!(
+        "  {:<field$}{:<width$}  {}",
+        "FIELD",
+        old_path.display(),
+        new_path.display()
+    );
+    for row in &rows {
+        let marker = if row.changed() { "*" } else { " " };
+        println!(
+            "{} {:<field$}{:<width$}  {}",
+            marker, row.field, row.old, row.new
+        );
+    }
+    let changed = rows.iter().filter(|row| row.changed()).count();
+    println!("{} of {} fields changed", changed, rows.len());
+    EXIT_OK
+}
+
+fn decoded(info: &RegisterInfo, reg: &Register) -> String {
+    match info.address {
+        STATUS_ADDRESS => set_bits(reg, &STATUS_BITS).join(","),
+        CONTROL_ADDRESS => set_bits(reg, &CONTROL_BITS).join(","),
+        STATE_ADDRESS => SSPAState::from_value(reg.value()).to_string(),
+        _ => String::new(),
+    }
+}
+
+fn set_bits(reg: &Register, names: &[&'static str; 15]) -> Vec<&'static str> {
+    bits(reg)
+        .iter()
+        .zip(names.iter())
+        .filter(|(set, name)| **set && !name.is_empty())
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+pub fn register_json(info: &RegisterInfo, reg: &Register) -> Value {
+    let mut value = json!({
+        "group": info.group,
+        "name": info.name,
+        "address": format!("0x{:02x}", info.address),
+        "raw": reg.raw(),
+        "value": reg.value(),
+        "parity": reg.state().to_string(),
+    });
+    match info.address {
+        STATUS_ADDRESS => value["bits"] = json!(set_bits(reg, &STATUS_BITS)),
+        CONTROL_ADDRESS => value["bits"] = json!(set_bits(reg, &CONTROL_BITS)),
+        STATE_ADDRESS => value["state"] = json!(decoded(info, reg)),
+        _ => {}
+    }
+    value
+}
+
+fn print_table(registers: &[(&RegisterInfo, Register)]) {
+    println!(
+        "{:<10} {:<24} {:<7} {:<7} {:>6} {:<12} DECODED",
+        "GROUP", "NAME", "ADDRESS", "RAW", "VALUE", "PARITY"
+    );
+    for (info, reg) in registers {
+        let line = format!(
+            "{:<10} {:<24} 0x{:02x}    0x{:04x}  {:>6} {:<12} {}",
+            info.group,
+            info.name,
+            info.address,
+            reg.raw(),
+            reg.value(),
+            reg.state(),
+            decoded(info, reg)
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    fn args(words: &[&str]) -> Result<Args, String> {
+        parse(
+            &words
+                .iter()
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn read_only_alone_starts_the_interface() {
+        let parsed = args(&["--read-only"]).unwrap();
+        assert!(parsed.read_only && parsed.command.is_none());
+        let parsed = args(&[]).unwrap();
+        assert!(!parsed.read_only && parsed.command.is_none());
+    }
+
+    #[test]
+    fn read_only_goes_anywhere_on_the_command_line() {
+        let parsed = args(&["set", "dac", "gan_1_current", "5", "--read-only"]).unwrap();
+        assert!(parsed.read_only);
+        assert!(matches!(
+            parsed.command.map(|cli| cli.request),
+            Some(Request::Set {
+                address: 0x34,
+                value: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn other_options_need_a_command() {
+        assert_eq!(args(&["--json"]).err().as_deref(), Some("invalid command"));
+        assert_eq!(
+            args(&["--read-only", "--yes"]).err().as_deref(),
+            Some("invalid command")
+        );
+    }
+
+    #[test]
+    fn set_refuses_the_serial_number_and_read_only_registers() {
+        for name in ["serial_number", "9"] {
+            assert_eq!(
+                args(&["set", "threshold", name, "5"]).err().as_deref(),
+                Some("threshold serial_number is not writable")
+            );
+        }
+        assert_eq!(
+            args(&["set", "adc", "output_power", "5"]).err().as_deref(),
+            Some("group adc is not writable")
+        );
+        assert_eq!(
+            args(&["set", "threshold", "over_current", "0x8000"])
+                .err()
+                .as_deref(),
+            Some("invalid value 0x8000")
+        );
+        assert!(matches!(
+            args(&["set", "threshold", "over_current", "500"]).map(|parsed| parsed.command),
+            Ok(Some(Cli {
+                request: Request::Set { value: 500, .. },
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_only_set_is_refused_before_connecting() {
+        let temp =
+            |name: &str| env::temp_dir().join(format!("sspa_tui_{}_{}", process::id(), name));
+        let (marker, audit_file) = (temp("set_connected"), temp("set_audit.log"));
+        let config = Config {
+            read_only: true,
+            ssh_command: format!("touch {}", marker.display()),
+            audit_file: Some(audit_file.clone()),
+            ..Config::default()
+        };
+        let cli = args(&["set", "dac", "gan_1_current", "5"])
+            .unwrap()
+            .command
+            .unwrap();
+        assert_eq!(run(cli, &config).await, EXIT_USAGE);
+        sleep(Duration::from_millis(100)).await;
+        let audit = fs::read_to_string(&audit_file).unwrap();
+        fs::remove_file(&audit_file).unwrap();
+        assert!(!marker.exists());
+        assert_eq!(audit.lines().count(), 1);
+        assert!(audit.contains("\"operation\":\"write dac.gan_1_current\""));
+        assert!(audit.contains("\"outcome\":\"read-only mode\""));
+        assert!(audit.contains("\"origin\":\"cli\""));
+    }
+}
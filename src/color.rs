@@ -1,37 +1,70 @@
+use crate::connection::ConnectionState;
 use crate::sspa::{Register, RegisterState, SSPAState};
-use tui::style::Color;
+use crate::theme::Theme;
+use tui::style::Style;
 
 pub trait ColorTrait {
-    fn color(&self) -> Color;
+    fn style(&self, theme: &Theme) -> Style;
+
+    fn symbol(&self) -> &'static str {
+        ""
+    }
 }
 
 impl ColorTrait for Register {
-    fn color(&self) -> Color {
+    fn style(&self, theme: &Theme) -> Style {
+        match self.state() {
+            RegisterState::Ok => theme.text,
+            RegisterState::Warning => theme.warning,
+            RegisterState::ParityError => theme.error,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
         match self.state() {
-            RegisterState::Ok => Color::White,
-            RegisterState::Warning => Color::Yellow,
-            RegisterState::ParityError => Color::Red,
+            RegisterState::Ok => "",
+            RegisterState::Warning => "▲",
+            RegisterState::ParityError => "✗",
         }
     }
 }
 
 impl ColorTrait for bool {
-    fn color(&self) -> Color {
+    fn style(&self, theme: &Theme) -> Style {
         if *self {
-            Color::White
+            theme.active
         } else {
-            Color::DarkGray
+            theme.inactive
         }
     }
 }
 
 impl ColorTrait for SSPAState {
-    fn color(&self) -> Color {
+    fn style(&self, theme: &Theme) -> Style {
+        match *self {
+            SSPAState::Invalid | SSPAState::Failure | SSPAState::Protection => theme.error,
+            SSPAState::Warning | SSPAState::ProtectionHW => theme.warning,
+            SSPAState::Boot => theme.info,
+            SSPAState::StandBy | SSPAState::Nominal | SSPAState::Disabled => theme.text,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            SSPAState::Invalid | SSPAState::Failure | SSPAState::Protection => "✗",
+            SSPAState::Warning | SSPAState::ProtectionHW => "▲",
+            SSPAState::Boot => "…",
+            SSPAState::StandBy | SSPAState::Nominal | SSPAState::Disabled => "",
+        }
+    }
+}
+
+impl ColorTrait for ConnectionState {
+    fn style(&self, theme: &Theme) -> Style {
         match *self {
-            SSPAState::Invalid | SSPAState::Failure | SSPAState::Protection => Color::Red,
-            SSPAState::Warning | SSPAState::ProtectionHW => Color::Yellow,
-            SSPAState::Boot => Color::Blue,
-            SSPAState::StandBy | SSPAState::Nominal | SSPAState::Disabled => Color::White,
+            ConnectionState::Connected => theme.ok,
+            ConnectionState::Reconnecting => theme.warning,
+            ConnectionState::Down => theme.error,
         }
     }
 }
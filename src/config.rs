@@ -0,0 +1,145 @@
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use crate::{layout::LayoutNode, limits::LimitRule, theme::ThemeName};
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub ssh_command: String,
+    pub read_only: bool,
+    pub scrollback: usize,
+    pub diagnostics_file: Option<PathBuf>,
+    pub audit_file: Option<PathBuf>,
+    pub metrics_port: Option<u16>,
+    pub api_socket: Option<PathBuf>,
+    pub tick_ms: u64,
+    pub max_fps: u32,
+    pub compile: CompileConfig,
+    pub keys: HashMap<String, Vec<String>>,
+    pub presets: BTreeMap<String, [u16; 3]>,
+    pub layout: Option<String>,
+    pub layouts: BTreeMap<String, LayoutNode>,
+    pub theme: ThemeName,
+    pub colors: BTreeMap<String, String>,
+    pub limits: Vec<LimitRule>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompileConfig {
+    pub build: String,
+    pub clean: String,
+    pub flash: String,
+}
+
+impl Default for CompileConfig {
+    fn default() -> CompileConfig {
+        CompileConfig {
+            build: "make".to_string(),
+            clean: "make clean".to_string(),
+            flash: "make flash".to_string(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            ssh_command: "ssh -tt dietpi@192.168.1.16 sspa -v -H -M".to_string(),
+            read_only: false,
+            scrollback: 10000,
+            diagnostics_file: None,
+            audit_file: None,
+            metrics_port: None,
+            api_socket: None,
+            tick_ms: 250,
+            max_fps: 30,
+            compile: CompileConfig::default(),
+            keys: HashMap::new(),
+            presets: BTreeMap::new(),
+            layout: None,
+            layouts: BTreeMap::new(),
+            theme: ThemeName::default(),
+            colors: BTreeMap::new(),
+            limits: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // a missing file means defaults, anything that is there has to parse
+    pub fn load() -> Result<Config, String> {
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.toml"),
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+}
+
+pub fn user_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn host_name() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .or_else(|_| env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("sspa_tui"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_is_default() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.read_only);
+        assert_eq!(config.scrollback, 10000);
+    }
+
+    #[test]
+    fn reads_keys() {
+        let config = Config::parse("read_only = true\n[compile]\nflash = \"st-flash\"").unwrap();
+        assert!(config.read_only);
+        assert_eq!(config.compile.flash, "st-flash");
+        assert_eq!(config.compile.build, "make");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(Config::parse("read_only = true\nscrollback = \"lots\"").is_err());
+        assert!(Config::parse("read_only = true\n[[limits]]\nname = 1").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::parse("read_olny = true").is_err());
+        assert!(Config::parse("[compile]\nflsah = \"make flash\"").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+use crate::{diagnostics::DiagnosticsSender, launcher::Launcher, scrollback::Line};
+
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+const DOWN_AFTER_FAILURES: u32 = 5;
+const STABLE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Connected,
+    Reconnecting,
+    Down,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => write!(f, "Connected"),
+            ConnectionState::Reconnecting => write!(f, "Reconnecting"),
+            ConnectionState::Down => write!(f, "Down"),
+        }
+    }
+}
+
+pub struct Connection {
+    launcher: Launcher,
+    command: String,
+    state: ConnectionState,
+    failures: u32,
+    retry_at: Option<Instant>,
+    last_output: Instant,
+    connected_at: Option<Instant>,
+    enabled: bool,
+    diagnostics: DiagnosticsSender,
+}
+
+impl Connection {
+    pub fn new(
+        command: &str,
+        buf_size: usize,
+        diagnostics: DiagnosticsSender,
+        wakeup: Arc<Notify>,
+    ) -> Connection {
+        // the first poll starts the link, nothing runs before the session does
+        Connection {
+            launcher: Launcher::new("ssh", buf_size, diagnostics.clone(), wakeup),
+            command: command.to_string(),
+            state: ConnectionState::Reconnecting,
+            failures: 0,
+            retry_at: Some(Instant::now()),
+            last_output: Instant::now(),
+            connected_at: None,
+            enabled: true,
+            diagnostics,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn launcher_mut(&mut self) -> &mut Launcher {
+        &mut self.launcher
+    }
+
+    pub fn send(&self, line: &str) -> bool {
+        self.state == ConnectionState::Connected && self.launcher.send(line)
+    }
+
+    pub fn connect(&mut self) {
+        if self.state == ConnectionState::Connected {
+            return;
+        }
+        self.enabled = true;
+        self.launcher.stop();
+        self.failures = 0;
+        self.retry_at = Some(Instant::now());
+        self.state = ConnectionState::Reconnecting;
+    }
+
+    pub fn disconnect(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.enabled = false;
+        self.launcher.stop();
+        self.retry_at = None;
+        self.connected_at = None;
+        self.state = ConnectionState::Down;
+        self.diagnostics.info("connection", "disconnected");
+    }
+
+    pub fn poll(&mut self) -> Vec<Line> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        if let Some(retry_at) = self.retry_at {
+            if now < retry_at {
+                return Vec::new();
+            }
+            self.retry_at = None;
+            self.last_output = now;
+            match self.failures {
+                0 => self.diagnostics.info("connection", "connecting"),
+                failures => self
+                    .diagnostics
+                    .info("connection", format!("reconnecting (attempt {})", failures)),
+            }
+            self.launcher.launch(&self.command);
+        }
+
+        let lines = self.launcher.poll();
+        if !lines.is_empty() {
+            self.last_output = now;
+            if self.connected_at.is_none() {
+                self.diagnostics.info("connection", "connected");
+                self.connected_at = Some(now);
+            }
+            self.state = ConnectionState::Connected;
+        }
+
+        let stalled = now.duration_since(self.last_output) > STALL_TIMEOUT;
+        if !self.launcher.is_running() || stalled {
+            self.launcher.stop();
+            if let Some(connected_at) = self.connected_at.take() {
+                if now.duration_since(connected_at) > STABLE_AFTER {
+                    self.failures = 0;
+                }
+            }
+            self.failures += 1;
+            let backoff = backoff(self.failures);
+            self.diagnostics.warning(
+                "connection",
+                format!(
+                    "{}, retrying in {}s",
+                    if stalled { "stream stalled" } else { "process exited" },
+                    backoff.as_secs()
+                ),
+            );
+            self.retry_at = Some(now + backoff);
+            self.state = if self.failures >= DOWN_AFTER_FAILURES {
+                ConnectionState::Down
+            } else {
+                ConnectionState::Reconnecting
+            };
+        }
+        lines
+    }
+}
+
+fn backoff(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    (BACKOFF_MIN * 2u32.pow(exponent)).min(BACKOFF_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_from_the_first_failure() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff(7), BACKOFF_MAX);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    audit::{Audit, AuditEntry},
+    sspa::Register,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DeviceCommand {
+    Write { address: u16, value: u16 },
+    HardReset,
+    PowerEnable(bool),
+    TnrLaunch { period: u16, pulse_width: u16, count: u16 },
+    TnrStop,
+}
+
+impl DeviceCommand {
+    pub fn to_line(self) -> String {
+        match self {
+            DeviceCommand::Write { address, value } => {
+                format!("w 0x{:02x} 0x{:04x}", address, Register::encode(value))
+            }
+            DeviceCommand::HardReset => "reset".to_string(),
+            DeviceCommand::PowerEnable(on) => format!("powen {}", on as u8),
+            DeviceCommand::TnrLaunch {
+                period,
+                pulse_width,
+                count,
+            } => format!("tnr {} {} {}", period, pulse_width, count),
+            DeviceCommand::TnrStop => "tnr stop".to_string(),
+        }
+    }
+}
+
+// every command reaches the unit through here, read-only mode refuses and audits them all
+pub fn guard(read_only: bool, audit: &mut Audit, entry: &AuditEntry) -> Result<(), String> {
+    if !read_only {
+        return Ok(());
+    }
+    let mut entry = entry.clone();
+    entry.outcome = "read-only mode".to_string();
+    audit.record(entry);
+    Err("read-only mode".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sspa::RegisterState;
+
+    #[test]
+    fn write_sets_the_parity_bit_for_odd_values() {
+        let write = |value| DeviceCommand::Write { address: 0x34, value }.to_line();
+        assert_eq!(write(5), "w 0x34 0x0005");
+        assert_eq!(write(7), "w 0x34 0x8007");
+        assert_eq!(write(0x8005), "w 0x34 0x0005");
+    }
+
+    #[test]
+    fn encoded_values_pass_the_parity_check() {
+        for value in 0..0x7FFF {
+            let reg = Register::new(Register::encode(value));
+            assert!(reg.state() == RegisterState::Ok, "0x{:04x}", value);
+            assert_eq!(reg.value(), value);
+        }
+    }
+
+    #[test]
+    fn flipped_bit_is_a_parity_error() {
+        let word = Register::encode(0x0123) ^ 0x0010;
+        assert!(Register::new(word).state() == RegisterState::ParityError);
+    }
+
+    #[test]
+    fn formats_unit_commands() {
+        assert_eq!(DeviceCommand::HardReset.to_line(), "reset");
+        assert_eq!(DeviceCommand::PowerEnable(true).to_line(), "powen 1");
+        assert_eq!(DeviceCommand::TnrStop.to_line(), "tnr stop");
+        let tnr = DeviceCommand::TnrLaunch {
+            period: 100,
+            pulse_width: 10,
+            count: 3,
+        };
+        assert_eq!(tnr.to_line(), "tnr 100 10 3");
+    }
+}
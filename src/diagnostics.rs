@@ -0,0 +1,199 @@
+use chrono::{DateTime, Local};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use crate::scrollback::{Line, Scrollback, Stream};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Info => write!(f, "INFO"),
+            Level::Warning => write!(f, "WARN"),
+            Level::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub source: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {}: {}",
+            self.time.format("%H:%M:%S%.3f"),
+            self.level,
+            self.source,
+            self.message
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct DiagnosticsSender {
+    tx: UnboundedSender<Diagnostic>,
+    wakeup: Arc<Notify>,
+}
+
+impl DiagnosticsSender {
+    pub fn send(&self, level: Level, source: &str, message: impl Into<String>) {
+        let _ = self.tx.send(Diagnostic {
+            time: Local::now(),
+            level,
+            source: source.to_string(),
+            message: message.into(),
+        });
+        self.wakeup.notify_one();
+    }
+
+    pub fn info(&self, source: &str, message: impl Into<String>) {
+        self.send(Level::Info, source, message);
+    }
+
+    pub fn warning(&self, source: &str, message: impl Into<String>) {
+        self.send(Level::Warning, source, message);
+    }
+
+    pub fn error(&self, source: &str, message: impl Into<String>) {
+        self.send(Level::Error, source, message);
+    }
+}
+
+pub struct Diagnostics {
+    tx: UnboundedSender<Diagnostic>,
+    rx: UnboundedReceiver<Diagnostic>,
+    wakeup: Arc<Notify>,
+    scrollback: Scrollback,
+    file: Option<File>,
+    started: Instant,
+    seq: u64,
+}
+
+impl Diagnostics {
+    pub fn new(buf_size: usize, path: Option<&Path>, wakeup: Arc<Notify>) -> Diagnostics {
+        let (tx, rx) = unbounded_channel();
+        let file = path.and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .ok()
+        });
+        let diagnostics = Diagnostics {
+            tx,
+            rx,
+            wakeup,
+            scrollback: Scrollback::new(buf_size),
+            file,
+            started: Instant::now(),
+            seq: 0,
+        };
+        if let (Some(path), None) = (path, &diagnostics.file) {
+            diagnostics.sender().error(
+                "diagnostics",
+                format!("failed to open log file {}", path.display()),
+            );
+        }
+        diagnostics
+    }
+
+    pub fn sender(&self) -> DiagnosticsSender {
+        DiagnosticsSender {
+            tx: self.tx.clone(),
+            wakeup: self.wakeup.clone(),
+        }
+    }
+
+    pub fn scrollback_mut(&mut self) -> &mut Scrollback {
+        &mut self.scrollback
+    }
+
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(diagnostic) = self.rx.try_recv() {
+            changed = true;
+            let line = diagnostic.to_string();
+            if let Some(file) = &mut self.file {
+                if writeln!(file, "{}", line).is_err() {
+                    self.file = None;
+                }
+            }
+            self.scrollback.push(Line {
+                stream: match diagnostic.level {
+                    Level::Info => Stream::Stdout,
+                    Level::Warning | Level::Error => Stream::Stderr,
+                },
+                timestamp: self.started.elapsed(),
+                seq: self.seq,
+                raw: line.clone().into_bytes(),
+                text: line,
+                partial: false,
+            });
+            self.seq += 1;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn warnings_and_errors_go_to_stderr() {
+        let mut diagnostics = Diagnostics::new(16, None, Arc::new(Notify::new()));
+        let sender = diagnostics.sender();
+        sender.info("test", "started");
+        sender.warning("test", "slow");
+        sender.error("test", "failed");
+        assert!(diagnostics.poll());
+        assert!(!diagnostics.poll());
+        let lines: Vec<(u64, Stream, String)> = diagnostics
+            .scrollback_mut()
+            .lines()
+            .map(|(seq, line)| (seq, line.stream, line.text.clone()))
+            .collect();
+        assert_eq!(
+            lines.iter().map(|(seq, stream, _)| (*seq, *stream)).collect::<Vec<_>>(),
+            [(0, Stream::Stdout), (1, Stream::Stderr), (2, Stream::Stderr)]
+        );
+        assert!(lines[0].2.ends_with(" INFO test: started"));
+        assert!(lines[1].2.ends_with(" WARN test: slow"));
+        assert!(lines[2].2.ends_with(" ERROR test: failed"));
+    }
+
+    #[test]
+    fn diagnostics_are_appended_to_the_log_file() {
+        let path = env::temp_dir().join(format!("sspa_tui_{}_diagnostics.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut diagnostics = Diagnostics::new(16, Some(&path), Arc::new(Notify::new()));
+        diagnostics.sender().warning("test", "slow");
+        diagnostics.poll();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.ends_with(" WARN test: slow\n"));
+    }
+}
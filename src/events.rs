@@ -1,7 +1,14 @@
-use crossterm::event::{read, Event, KeyCode, KeyModifiers};
-use tokio::sync::mpsc::{channel, error::TryRecvError, Receiver, Sender};
+use crossterm::event::{
+    read, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind,
+};
+use std::thread;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 
-use crate::state::{StateKeeper, StateTransition};
+use crate::{
+    action::Action,
+    keymap::{Context, Keymap},
+    state::StateKeeper,
+};
 
 pub struct Events {
     rx: Receiver<Event>,
@@ -10,99 +17,151 @@ pub struct Events {
 impl Events {
     pub fn new() -> Events {
         let (tx, rx) = channel(128);
-        tokio::spawn(async move {
-            event_thread(tx)
-                .await
-                .expect("ERROR: crossterm event reader failed");
+        thread::spawn(move || {
+            event_thread(tx).expect("ERROR: crossterm event reader failed");
         });
         Events { rx }
     }
 
-    pub fn hanlde(&mut self, state: &mut StateKeeper) -> bool {
-        match self.rx.try_recv() {
-            Ok(events) => {
-                state.widget_select(widget_selection(&events));
-                state.element_select(element_selection(&events));
+    // events fed by the caller instead of the terminal
+    #[cfg(test)]
+    pub fn channel() -> (Sender<Event>, Events) {
+        let (tx, rx) = channel(128);
+        (tx, Events { rx })
+    }
+
+    pub async fn next(&mut self) -> Option<Event> {
+        self.rx.recv().await
+    }
+
+    pub fn handle(&self, event: Event, state: &mut StateKeeper) -> bool {
+        if state.help() {
+            if let Event::Key(_) = event {
+                state.dispatch(Action::Help);
+            }
+            return false;
+        }
+        if state.snapshot_diff().is_some() {
+            if let Event::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter if state.restore_pending() => state.confirm_restore(false),
+                    KeyCode::Char('s') if state.restore_pending() => state.confirm_restore(true),
+                    KeyCode::Up | KeyCode::Char('k') => state.scroll_diff(false),
+                    KeyCode::Down | KeyCode::Char('j') => state.scroll_diff(true),
+                    _ => state.close_diff(),
+                }
+            }
+            return false;
+        }
+        if state.sign_off().is_some() {
+            if let Event::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter => {
+                        let _ = state.sign_report();
+                    }
+                    KeyCode::Esc => state.leave_unsigned(),
+                    _ => {}
+                }
+            }
+            return false;
+        }
+        if state.palette_input().is_some() {
+            if let Event::Key(key) = event {
+                palette_input(&key, state);
+            }
+            return false;
+        }
+        if state.search_input().is_some() {
+            if let Event::Key(key) = event {
+                search_input(&key, state);
+            }
+            return false;
+        }
+        if state.edit_input().is_some() {
+            if let Event::Key(key) = event {
+                edit_input(&key, state);
+            }
+            return false;
+        }
+        if let Event::Key(key) = event {
+            if key.code == KeyCode::Esc && state.script_running().is_some() {
+                state.abort_script();
+                return false;
+            }
+        }
+        let context = match state.selected_pane_mut() {
+            Some(_) => Context::Pane,
+            None => Context::List,
+        };
+        match event_action(&event, state.keymap(), context) {
+            Some(Action::Quit) => !state.clear_search(),
+            Some(action) => {
+                state.dispatch(action);
                 false
             }
-            Err(e) => match e {
-                TryRecvError::Empty => false,
-                _ => true,
-            },
+            None => false,
         }
     }
 }
 
-fn widget_selection(event: &Event) -> Option<StateTransition> {
-    if let Event::Key(key) = event {
-        if key.modifiers != KeyModifiers::CONTROL {
-            return None;
-        }
-        match key.code {
-            KeyCode::Up => return Some(StateTransition::Up),
-            KeyCode::Down => return Some(StateTransition::Down),
-            KeyCode::Left => return Some(StateTransition::Left),
-            KeyCode::Right => return Some(StateTransition::Right),
-            KeyCode::Char(c) => match c {
-                'k' | 'K' => return Some(StateTransition::Up),
-                'j' | 'J' => return Some(StateTransition::Down),
-                'h' | 'H' => return Some(StateTransition::Left),
-                'l' | 'L' => return Some(StateTransition::Right),
-                _ => {}
-            },
-            _ => {}
+fn search_input(key: &KeyEvent, state: &mut StateKeeper) {
+    match key.code {
+        KeyCode::Char(c) => state.edit_search(Some(c)),
+        KeyCode::Backspace => state.edit_search(None),
+        KeyCode::Enter => state.finish_search(),
+        KeyCode::Esc => {
+            state.clear_search();
         }
+        _ => {}
     }
-    None
 }
 
-fn element_selection(event: &Event) -> Option<StateTransition> {
-    if let Event::Key(key) = event {
-        if key.modifiers != KeyModifiers::NONE {
-            return None;
-        }
-        match key.code {
-            KeyCode::Up => return Some(StateTransition::Up),
-            KeyCode::Down => return Some(StateTransition::Down),
-            KeyCode::Left => return Some(StateTransition::Left),
-            KeyCode::Right => return Some(StateTransition::Right),
-            KeyCode::Char(c) => match c {
-                'k' | 'K' => return Some(StateTransition::Up),
-                'j' | 'J' => return Some(StateTransition::Down),
-                'h' | 'H' => return Some(StateTransition::Left),
-                'l' | 'L' => return Some(StateTransition::Right),
-                _ => {}
-            },
-            _ => {}
+fn palette_input(key: &KeyEvent, state: &mut StateKeeper) {
+    match key.code {
+        KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => state.move_palette(true),
+        KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => state.move_palette(false),
+        KeyCode::Char(c) => state.edit_palette(Some(c)),
+        KeyCode::Backspace => state.edit_palette(None),
+        KeyCode::Up => state.move_palette(true),
+        KeyCode::Down => state.move_palette(false),
+        KeyCode::Tab => state.complete_palette(),
+        KeyCode::Enter => state.run_palette(),
+        KeyCode::Esc => state.close_palette(),
+        _ => {}
+    }
+}
+
+fn edit_input(key: &KeyEvent, state: &mut StateKeeper) {
+    match key.code {
+        KeyCode::Char(c) => state.edit_value(Some(c)),
+        KeyCode::Backspace => state.edit_value(None),
+        KeyCode::Enter => state.commit_edit(),
+        KeyCode::Esc => state.cancel_edit(),
+        _ => {}
+    }
+}
+
+fn event_action(event: &Event, keymap: &Keymap, context: Context) -> Option<Action> {
+    match event {
+        Event::Key(key) => keymap.action(key, context),
+        Event::Mouse(mouse) => {
+            let (column, row) = (mouse.column, mouse.row);
+            match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => Some(Action::MouseClick { column, row }),
+                MouseEventKind::ScrollUp => Some(Action::MouseScroll { column, row, up: true }),
+                MouseEventKind::ScrollDown => Some(Action::MouseScroll { column, row, up: false }),
+                _ => None,
+            }
         }
+        _ => None,
     }
-    None
 }
 
-async fn event_thread(tx: Sender<Event>) -> Result<(), Box<dyn std::error::Error>> {
+fn event_thread(tx: Sender<Event>) -> Result<(), Box<dyn std::error::Error>> {
     loop {
         let event = read()?;
-        match event {
-            Event::Key(key_event) => {
-                match key_event.code {
-                    KeyCode::Char(c) => match c {
-                        'q' | 'Q' => {
-                            break;
-                        }
-                        _ => {}
-                    },
-                    KeyCode::Esc => {
-                        break;
-                    }
-                    _ => {}
-                }
-                tx.send(event).await?;
-            }
-            Event::Mouse(_) => {
-                tx.send(event).await?;
-            }
-            _ => {}
+        if let Event::Key(_) | Event::Mouse(_) | Event::Resize(_, _) = event {
+            tx.blocking_send(event)?;
         }
     }
-    Ok(())
 }
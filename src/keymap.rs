@@ -0,0 +1,290 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+use crate::{action::Action, state::StateTransition};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    Global,
+    List,
+    Pane,
+}
+
+impl Context {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::List => "list",
+            Context::Pane => "pane",
+        }
+    }
+}
+
+const BINDABLE: [(&str, Context, Action); 28] = [
+    ("quit", Context::Global, Action::Quit),
+    ("help", Context::Global, Action::Help),
+    ("palette", Context::Global, Action::OpenPalette),
+    ("zoom", Context::Global, Action::ToggleZoom),
+    ("next_layout", Context::Global, Action::NextLayout),
+    ("activate", Context::Global, Action::Activate),
+    ("focus_up", Context::Global, Action::FocusWidget(StateTransition::Up)),
+    ("focus_down", Context::Global, Action::FocusWidget(StateTransition::Down)),
+    ("focus_left", Context::Global, Action::FocusWidget(StateTransition::Left)),
+    ("focus_right", Context::Global, Action::FocusWidget(StateTransition::Right)),
+    ("focus_next", Context::Global, Action::FocusNext),
+    ("focus_previous", Context::Global, Action::FocusPrevious),
+    ("select_up", Context::List, Action::MoveSelection(StateTransition::Up)),
+    ("select_down", Context::List, Action::MoveSelection(StateTransition::Down)),
+    ("select_left", Context::List, Action::MoveSelection(StateTransition::Left)),
+    ("select_right", Context::List, Action::MoveSelection(StateTransition::Right)),
+    ("scroll_up", Context::Pane, Action::ScrollUp(1)),
+    ("scroll_down", Context::Pane, Action::ScrollDown(1)),
+    ("page_up", Context::Pane, Action::PageUp),
+    ("page_down", Context::Pane, Action::PageDown),
+    ("scroll_top", Context::Pane, Action::ScrollTop),
+    ("toggle_follow", Context::Pane, Action::ToggleFollow),
+    ("toggle_timestamps", Context::Pane, Action::ToggleTimestamps),
+    ("cycle_filter", Context::Pane, Action::CycleFilter),
+    ("toggle_hex", Context::Pane, Action::ToggleHex),
+    ("search", Context::Pane, Action::StartSearch),
+    ("next_match", Context::Pane, Action::NextMatch),
+    ("previous_match", Context::Pane, Action::PreviousMatch),
+];
+
+const DEFAULT_KEYS: [(&str, &[&str]); 28] = [
+    ("quit", &["q", "Q", "esc"]),
+    ("help", &["?"]),
+    ("palette", &[":", "ctrl+p"]),
+    ("zoom", &["z"]),
+    ("next_layout", &["v"]),
+    ("activate", &["enter", "space"]),
+    ("focus_up", &["ctrl+up", "ctrl+k", "ctrl+K"]),
+    ("focus_down", &["ctrl+down", "ctrl+j", "ctrl+J"]),
+    ("focus_left", &["ctrl+left", "ctrl+h", "ctrl+H"]),
+    ("focus_right", &["ctrl+right", "ctrl+l", "ctrl+L"]),
+    ("focus_next", &["tab"]),
+    ("focus_previous", &["backtab"]),
+    ("select_up", &["up", "k", "K"]),
+    ("select_down", &["down", "j", "J"]),
+    ("select_left", &["left", "h", "H"]),
+    ("select_right", &["right", "l", "L"]),
+    ("scroll_up", &["up", "k"]),
+    ("scroll_down", &["down", "j"]),
+    ("page_up", &["pageup"]),
+    ("page_down", &["pagedown"]),
+    ("scroll_top", &["home", "g"]),
+    ("toggle_follow", &["f"]),
+    ("toggle_timestamps", &["t"]),
+    ("cycle_filter", &["s"]),
+    ("toggle_hex", &["x"]),
+    ("search", &["/"]),
+    ("next_match", &["n"]),
+    ("previous_match", &["N"]),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn from_event(event: &KeyEvent) -> Key {
+        let mut modifiers = event.modifiers;
+        if let KeyCode::Char(_) | KeyCode::BackTab = event.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Key> {
+        let mut modifiers = KeyModifiers::NONE;
+        let (prefix, name) = match text.ends_with('+') {
+            true => (&text[..text.len() - 1], "+"),
+            false => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let parts = prefix.split('+').filter(|part| !part.is_empty());
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" => modifiers.insert(KeyModifiers::CONTROL),
+                "alt" => modifiers.insert(KeyModifiers::ALT),
+                "shift" => modifiers.insert(KeyModifiers::SHIFT),
+                _ => return None,
+            }
+        }
+        let code = match name.to_lowercase().as_str() {
+            "esc" => KeyCode::Esc,
+            "enter" => KeyCode::Enter,
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            _ if name.chars().count() == 1 => KeyCode::Char(name.chars().next()?),
+            f if f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+            _ => return None,
+        };
+        let code = match code {
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            code => code,
+        };
+        if let KeyCode::Char(_) | KeyCode::BackTab = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Some(Key { code, modifiers })
+    }
+}
+
+pub struct Binding {
+    pub name: &'static str,
+    pub context: Context,
+    pub action: Action,
+    pub keys: Vec<String>,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    map: HashMap<(Context, Key), Action>,
+}
+
+impl Keymap {
+    pub fn new(config: &HashMap<String, Vec<String>>) -> (Keymap, Vec<String>) {
+        let mut problems = Vec::new();
+        for name in config.keys() {
+            if !BINDABLE.iter().any(|(bindable, _, _)| bindable == name) {
+                problems.push(format!("unknown action {} in key bindings", name));
+            }
+        }
+        let mut bindings: Vec<Binding> = BINDABLE
+            .iter()
+            .zip(DEFAULT_KEYS.iter())
+            .map(|((name, context, action), (_, keys))| Binding {
+                name,
+                context: *context,
+                action: *action,
+                keys: match config.get(*name) {
+                    Some(keys) => keys.clone(),
+                    None => keys.iter().map(|key| key.to_string()).collect(),
+                },
+            })
+            .collect();
+        bindings.sort_by_key(|binding| !config.contains_key(binding.name));
+
+        let mut map = HashMap::new();
+        let mut owners: HashMap<(Context, Key), &str> = HashMap::new();
+        for binding in &bindings {
+            for text in &binding.keys {
+                let key = match Key::parse(text) {
+                    Some(key) => key,
+                    None => {
+                        problems.push(format!("invalid key {} for {}", text, binding.name));
+                        continue;
+                    }
+                };
+                let shadowed = match binding.context {
+                    Context::Global => vec![Context::Global, Context::List, Context::Pane],
+                    context => vec![Context::Global, context],
+                };
+                let conflict = shadowed
+                    .iter()
+                    .find_map(|context| owners.get(&(*context, key)).copied());
+                if let Some(owner) = conflict.filter(|owner| *owner != binding.name) {
+                    problems.push(format!(
+                        "key {} for {} conflicts with {}",
+                        text, binding.name, owner
+                    ));
+                    continue;
+                }
+                owners.insert((binding.context, key), binding.name);
+                map.insert((binding.context, key), binding.action);
+            }
+        }
+        bindings.sort_by_key(|binding| {
+            BINDABLE
+                .iter()
+                .position(|(name, _, _)| *name == binding.name)
+        });
+        (Keymap { bindings, map }, problems)
+    }
+
+    pub fn action(&self, event: &KeyEvent, context: Context) -> Option<Action> {
+        let key = Key::from_event(event);
+        self.map
+            .get(&(context, key))
+            .or_else(|| self.map.get(&(Context::Global, key)))
+            .copied()
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(bindings: &[(&str, &[&str])]) -> (Keymap, Vec<String>) {
+        let config = bindings
+            .iter()
+            .map(|(name, keys)| {
+                let keys = keys.iter().map(|key| key.to_string()).collect();
+                (name.to_string(), keys)
+            })
+            .collect();
+        Keymap::new(&config)
+    }
+
+    fn press(keymap: &Keymap, c: char, context: Context) -> Option<Action> {
+        let event = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        keymap.action(&event, context)
+    }
+
+    #[test]
+    fn defaults_do_not_conflict() {
+        let (_, problems) = keymap(&[]);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn configured_key_takes_over_a_default() {
+        let (keymap, problems) = keymap(&[("zoom", &["q"])]);
+        assert_eq!(problems, ["key q for quit conflicts with zoom"]);
+        assert_eq!(press(&keymap, 'q', Context::Global), Some(Action::ToggleZoom));
+    }
+
+    #[test]
+    fn global_key_shadows_pane_keys() {
+        let (keymap, problems) = keymap(&[("toggle_hex", &["z"])]);
+        assert_eq!(problems, ["key z for zoom conflicts with toggle_hex"]);
+        assert_eq!(press(&keymap, 'z', Context::Pane), Some(Action::ToggleHex));
+        assert_eq!(press(&keymap, 'z', Context::List), None);
+    }
+
+    #[test]
+    fn reports_unknown_actions_and_keys() {
+        let (_, problems) = keymap(&[("launch", &["x"]), ("zoom", &["hyper+z"])]);
+        assert!(problems.contains(&"unknown action launch in key bindings".to_string()));
+        assert!(problems.contains(&"invalid key hyper+z for zoom".to_string()));
+    }
+
+    #[test]
+    fn parses_modifiers_and_literal_plus() {
+        let key = |code, modifiers| Some(Key { code, modifiers });
+        assert!(Key::parse("ctrl+p") == key(KeyCode::Char('p'), KeyModifiers::CONTROL));
+        assert!(Key::parse("shift+tab") == key(KeyCode::BackTab, KeyModifiers::NONE));
+        assert!(Key::parse("+") == key(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert!(Key::parse("ctrl++") == key(KeyCode::Char('+'), KeyModifiers::CONTROL));
+        assert!(Key::parse("f5") == key(KeyCode::F(5), KeyModifiers::NONE));
+    }
+}
@@ -1,26 +1,95 @@
-use polling::{Event, Poller};
-use ringbuf::{HeapRb, Rb};
-use std::process::Stdio;
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncReadExt, AsyncWriteExt},
     process::Command,
-    sync::mpsc::{channel, error::TryRecvError, Receiver},
+    sync::{
+        mpsc::{channel, error::TryRecvError, unbounded_channel, Receiver, UnboundedSender},
+        Notify,
+    },
+    task::JoinHandle,
+    time::sleep_until,
+};
+
+use crate::{
+    diagnostics::DiagnosticsSender,
+    scrollback::{Line, Scrollback, Stream},
 };
 
+const PARTIAL_LINE_IDLE: Duration = Duration::from_millis(100);
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
 pub struct Launcher {
-    ring_buffer: HeapRb<String>,
-    rx: Option<Receiver<String>>,
+    name: String,
+    scrollback: Scrollback,
+    rx: Option<Receiver<Line>>,
+    stdin: Option<UnboundedSender<String>>,
+    task: Option<JoinHandle<()>>,
+    exit_status: Arc<Mutex<Option<ExitStatus>>>,
+    // one clock and counter for the pane, so relaunches keep counting forward
+    started: Instant,
+    seq: Arc<AtomicU64>,
+    diagnostics: DiagnosticsSender,
+    wakeup: Arc<Notify>,
 }
 
 impl Launcher {
-    pub fn new(buf_size: usize) -> Launcher {
+    pub fn new(
+        name: &str,
+        buf_size: usize,
+        diagnostics: DiagnosticsSender,
+        wakeup: Arc<Notify>,
+    ) -> Launcher {
         Launcher {
-            ring_buffer: HeapRb::new(buf_size),
+            name: name.to_string(),
+            scrollback: Scrollback::new(buf_size),
             rx: None,
+            stdin: None,
+            task: None,
+            exit_status: Arc::new(Mutex::new(None)),
+            started: Instant::now(),
+            seq: Arc::new(AtomicU64::new(0)),
+            diagnostics,
+            wakeup,
         }
     }
 
-    pub fn read(&mut self) -> String {
+    pub fn scrollback_mut(&mut self) -> &mut Scrollback {
+        &mut self.scrollback
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        *self.exit_status.lock().expect("ERROR: launcher lock poisoned")
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            self.diagnostics.info(&self.name, "process stopped");
+        }
+        self.rx = None;
+        self.stdin = None;
+    }
+
+    pub fn send(&self, line: &str) -> bool {
+        match &self.stdin {
+            Some(stdin) => stdin.send(line.to_string()).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Line> {
         let mut vec = Vec::new();
         if let Some(rx) = &mut self.rx {
             loop {
@@ -29,115 +98,250 @@ impl Launcher {
                         vec.push(line);
                     }
                     Err(e) => {
-                        match e {
-                            TryRecvError::Empty => {}
-                            _ => self.rx = None,
+                        if let TryRecvError::Disconnected = e {
+                            self.rx = None;
+                            self.stdin = None;
+                            self.task = None;
                         }
                         break;
                     }
                 }
             }
         }
-        for line in vec {
-            self.ring_buffer.push_overwrite(line);
-        }
-        let mut ret = String::new();
-        for line in self.ring_buffer.iter() {
-            ret.push_str(line);
+        for line in &vec {
+            self.scrollback.push(line.clone());
         }
-        ret
+        vec
     }
 
     pub fn launch(&mut self, command: &str) {
         let mut command: Vec<&str> = command.split(' ').rev().collect();
         if let Some(program) = command.pop() {
-            let (tx, rx) = channel(128);
-            self.rx = Some(rx);
             let mut output = Command::new(program);
             for arg in command.iter().rev() {
                 output.arg(arg);
             }
-            tokio::spawn(async move {
-                let mut output = output
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()
-                    .expect("ERROR: Launcher failed to execute process");
-                let stdout = output.stdout.take().unwrap();
-                let stderr = output.stderr.take().unwrap();
-                let mut reader_out = BufReader::new(stdout);
-                let mut reader_err = BufReader::new(stderr);
-                let key_out = 1;
-                let key_err = 2;
-                let mut out_closed = false;
-                let mut err_closed = false;
-
-                let poller = Poller::new().unwrap();
-                poller
-                    .add(reader_out.get_ref(), Event::readable(key_out))
-                    .unwrap();
-                poller
-                    .add(reader_err.get_ref(), Event::readable(key_err))
-                    .unwrap();
-
-                let mut events = Vec::new();
-                loop {
-                    events.clear();
-                    poller.wait(&mut events, None).unwrap();
-                    for ev in &events {
-                        if ev.key == key_out {
-                            let mut str = String::new();
-                            let len = match reader_out.read_line(&mut str).await {
-                                Ok(len) => len,
-                                Err(e) => {
-                                    println!("stdout read returned error: {}", e);
-                                    0
-                                }
-                            };
-                            if len == 0 {
-                                println!("stdout closed (len is null)");
-                                out_closed = true;
-                                poller.delete(reader_out.get_ref()).unwrap();
-                            } else {
-                                tx.send(str)
-                                    .await
-                                    .expect("ERROR: Failed to send stdout line");
-                                poller
-                                    .modify(reader_out.get_ref(), Event::readable(key_out))
-                                    .unwrap();
-                            }
-                        }
+            self.spawn(output, program);
+        }
+    }
+
+    pub fn launch_shell(&mut self, script: &str) {
+        let mut output = Command::new("sh");
+        output.arg("-c").arg(script);
+        self.spawn(output, script);
+    }
+
+    fn spawn(&mut self, mut output: Command, program: &str) {
+        self.stop();
+        let (tx, rx) = channel(128);
+        self.rx = Some(rx);
+        let (stdin_tx, mut stdin_rx) = unbounded_channel::<String>();
+        self.stdin = Some(stdin_tx);
+        let diagnostics = self.diagnostics.clone();
+        let wakeup = self.wakeup.clone();
+        let name = self.name.clone();
+        let program = program.to_string();
+        self.exit_status = Arc::new(Mutex::new(None));
+        let exit_status = self.exit_status.clone();
+        let started = self.started;
+        let seq = self.seq.clone();
+        self.task = Some(tokio::spawn(async move {
+            let mut output = match output
+                .kill_on_drop(true)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+            {
+                Ok(output) => output,
+                Err(e) => {
+                    diagnostics.error(&name, format!("failed to execute {}: {}", program, e));
+                    return;
+                }
+            };
+            diagnostics.info(&name, format!("started {}", program));
+            let mut stdin = output.stdin.take().unwrap();
+            let mut stdin_closed = false;
+            let mut stdout = output.stdout.take().unwrap();
+            let mut stderr = output.stderr.take().unwrap();
+            let mut out = Capture::new(Stream::Stdout);
+            let mut err = Capture::new(Stream::Stderr);
+            let mut buf_out = [0u8; 4096];
+            let mut buf_err = [0u8; 4096];
 
-                        if ev.key == key_err {
-                            let mut str = String::new();
-                            let len = match reader_err.read_line(&mut str).await {
-                                Ok(len) => len,
-                                Err(e) => {
-                                    println!("stderr read returned error: {}", e);
-                                    0
+            loop {
+                if out.closed && err.closed {
+                    break;
+                }
+                // the partial line timer runs from the last byte of whichever stream went quiet first
+                let idle_at = out.idle_at.into_iter().chain(err.idle_at).min();
+                let lines = tokio::select! {
+                    read = stdout.read(&mut buf_out), if !out.closed => {
+                        out.receive(read.map(|n| &buf_out[..n]), &diagnostics, &name)
+                    }
+                    read = stderr.read(&mut buf_err), if !err.closed => {
+                        err.receive(read.map(|n| &buf_err[..n]), &diagnostics, &name)
+                    }
+                    input = stdin_rx.recv(), if !stdin_closed => {
+                        match input {
+                            Some(input) => {
+                                let written = stdin.write_all(format!("{}\n", input).as_bytes()).await;
+                                if let Err(e) = written {
+                                    diagnostics
+                                        .warning(&name, format!("stdin write returned error: {}", e));
+                                    stdin_closed = true;
                                 }
-                            };
-                            if len == 0 {
-                                println!("stderr closed (len is null)");
-                                err_closed = true;
-                                poller.delete(reader_err.get_ref()).unwrap();
-                            } else {
-                                tx.send(str)
-                                    .await
-                                    .expect("ERROR: Failed to send stderr line");
-                                poller
-                                    .modify(reader_err.get_ref(), Event::readable(key_err))
-                                    .unwrap();
                             }
+                            None => stdin_closed = true,
                         }
+                        Vec::new()
                     }
-
-                    if err_closed || out_closed {
-                        println!("Stream closed, exiting process thread");
-                        break;
+                    _ = sleep_until(idle_at.unwrap_or_else(Instant::now).into()), if idle_at.is_some() => {
+                        let now = Instant::now();
+                        out.flush_partial(now).into_iter().chain(err.flush_partial(now)).collect()
+                    }
+                    else => break,
+                };
+                for (stream, raw, partial) in lines {
+                    let line = Line {
+                        stream,
+                        timestamp: started.elapsed(),
+                        seq: seq.fetch_add(1, Ordering::Relaxed),
+                        text: String::from_utf8_lossy(&raw).into_owned(),
+                        raw,
+                        partial,
+                    };
+                    if tx.send(line).await.is_err() {
+                        return;
                     }
+                    wakeup.notify_one();
                 }
-            });
+            }
+
+            match output.wait().await {
+                Ok(status) => {
+                    *exit_status.lock().expect("ERROR: launcher lock poisoned") = Some(status);
+                    diagnostics.info(&name, format!("process exited with {}", status))
+                }
+                Err(e) => diagnostics.error(&name, format!("failed to wait for process: {}", e)),
+            }
+        }));
+    }
+}
+
+struct Capture {
+    stream: Stream,
+    pending: Vec<u8>,
+    flushed: usize,
+    closed: bool,
+    idle_at: Option<Instant>,
+}
+
+impl Capture {
+    fn new(stream: Stream) -> Capture {
+        Capture {
+            stream,
+            pending: Vec::new(),
+            flushed: 0,
+            closed: false,
+            idle_at: None,
+        }
+    }
+
+    fn has_unflushed(&self) -> bool {
+        self.pending.len() > self.flushed
+    }
+
+    fn receive(
+        &mut self,
+        read: io::Result<&[u8]>,
+        diagnostics: &DiagnosticsSender,
+        name: &str,
+    ) -> Vec<(Stream, Vec<u8>, bool)> {
+        let stream_name = match self.stream {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        };
+        let bytes = match read {
+            Ok(bytes) if !bytes.is_empty() => bytes,
+            Ok(_) => {
+                diagnostics.info(name, format!("{} closed", stream_name));
+                return self.finish();
+            }
+            Err(e) => {
+                diagnostics.error(name, format!("{} read returned error: {}", stream_name, e));
+                return self.finish();
+            }
+        };
+        let mut lines = Vec::new();
+        for &byte in bytes {
+            if byte != b'\n' {
+                self.pending.push(byte);
+            }
+            if byte == b'\n' || self.pending.len() >= MAX_LINE_LENGTH {
+                self.flushed = 0;
+                lines.push((self.stream, std::mem::take(&mut self.pending), false));
+            }
+        }
+        self.idle_at = self
+            .has_unflushed()
+            .then(|| Instant::now() + PARTIAL_LINE_IDLE);
+        lines
+    }
+
+    fn flush_partial(&mut self, now: Instant) -> Option<(Stream, Vec<u8>, bool)> {
+        if !self.has_unflushed() || self.idle_at.is_some_and(|idle_at| idle_at > now) {
+            return None;
+        }
+        self.idle_at = None;
+        self.flushed = self.pending.len();
+        Some((self.stream, self.pending.clone(), true))
+    }
+
+    fn finish(&mut self) -> Vec<(Stream, Vec<u8>, bool)> {
+        self.closed = true;
+        self.flushed = 0;
+        self.idle_at = None;
+        if self.pending.is_empty() {
+            return Vec::new();
         }
+        vec![(self.stream, std::mem::take(&mut self.pending), false)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+
+    fn sender() -> DiagnosticsSender {
+        Diagnostics::new(16, None, Arc::new(Notify::new())).sender()
+    }
+
+    #[test]
+    fn partial_line_waits_for_its_own_deadline() {
+        let diagnostics = sender();
+        let mut capture = Capture::new(Stream::Stdout);
+        let start = Instant::now();
+        assert!(capture.receive(Ok(b"0x01: 0x"), &diagnostics, "ssh").is_empty());
+        let idle_at = capture.idle_at.expect("partial line arms the deadline");
+        assert!(idle_at >= start + PARTIAL_LINE_IDLE);
+        assert_eq!(capture.flush_partial(idle_at - Duration::from_millis(1)), None);
+        assert_eq!(
+            capture.flush_partial(idle_at),
+            Some((Stream::Stdout, b"0x01: 0x".to_vec(), true))
+        );
+        assert_eq!(capture.idle_at, None);
+        assert_eq!(capture.flush_partial(idle_at + PARTIAL_LINE_IDLE), None);
+    }
+
+    #[test]
+    fn complete_line_clears_the_deadline() {
+        let diagnostics = sender();
+        let mut capture = Capture::new(Stream::Stderr);
+        capture.receive(Ok(b"0x01"), &diagnostics, "ssh");
+        let lines = capture.receive(Ok(b": 0x0000\n"), &diagnostics, "ssh");
+        assert_eq!(lines, vec![(Stream::Stderr, b"0x01: 0x0000".to_vec(), false)]);
+        assert_eq!(capture.idle_at, None);
     }
 }
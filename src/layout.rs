@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+use crate::config::{config_dir, Config};
+
+pub const DEFAULT_LAYOUT: &str = "default";
+
+const BUILTIN_LAYOUTS: &str = r#"
+[firmware]
+split = "horizontal"
+children = [
+    { size = 40, split = "vertical", children = [
+        { widget = "state", size = 3 },
+        { widget = "firmware", size = 3 },
+        { widget = "compile" },
+        { widget = "hard_reset", size = 3 },
+    ] },
+    { split = "vertical", children = [
+        { widget = "terminal", size = "70%" },
+        { widget = "log" },
+    ] },
+]
+
+[test]
+split = "horizontal"
+children = [
+    { size = 40, split = "vertical", children = [
+        { widget = "adc", size = 10 },
+        { widget = "state", size = 3 },
+        { widget = "ext" },
+    ] },
+    { split = "vertical", children = [
+        { widget = "dac", size = 12 },
+        { widget = "log" },
+    ] },
+]
+"#;
+
+const PANELS: [(&str, usize); 15] = [
+    ("adc", 1),
+    ("registers", 2),
+    ("state", 3),
+    ("firmware", 5),
+    ("hard_reset", 6),
+    ("ext", 7),
+    ("presets", 8),
+    ("compile", 9),
+    ("dac", 10),
+    ("offsets", 11),
+    ("control", 12),
+    ("ssh", 13),
+    ("terminal", 14),
+    ("log", 15),
+    ("audit", 16),
+];
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Size {
+    Length(u16),
+    Relative(String),
+}
+
+#[derive(Clone, Deserialize)]
+pub struct LayoutNode {
+    pub widget: Option<String>,
+    pub split: Option<Split>,
+    pub size: Option<Size>,
+    #[serde(default)]
+    pub children: Vec<LayoutNode>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Session {
+    layout: Option<String>,
+}
+
+impl LayoutNode {
+    fn constraint(&self) -> Result<Constraint, String> {
+        match &self.size {
+            None => Ok(Constraint::Min(0)),
+            Some(Size::Length(length)) => Ok(Constraint::Length(*length)),
+            Some(Size::Relative(size)) => size
+                .strip_suffix('%')
+                .and_then(|percent| percent.trim().parse().ok())
+                .filter(|percent| *percent <= 100)
+                .map(Constraint::Percentage)
+                .ok_or(format!("invalid size {}", size)),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.constraint()?;
+        match (&self.widget, self.split) {
+            (Some(widget), None) => match panel_index(widget) {
+                Some(_) if self.children.is_empty() => Ok(()),
+                Some(_) => Err(format!("widget {} cannot have children", widget)),
+                None => Err(format!("unknown widget {}", widget)),
+            },
+            (None, Some(_)) if !self.children.is_empty() => {
+                self.children.iter().try_for_each(LayoutNode::validate)
+            }
+            (None, Some(_)) => Err("split without children".to_string()),
+            _ => Err("node needs either a widget or a split".to_string()),
+        }
+    }
+
+    // panel indices in the order they appear in the tree
+    pub fn panels(&self) -> Vec<usize> {
+        match self.widget.as_deref().and_then(panel_index) {
+            Some(index) => vec![index],
+            None => self.children.iter().flat_map(LayoutNode::panels).collect(),
+        }
+    }
+
+    pub fn split(&self, area: Rect, chunks: &mut [Rect]) {
+        if let Some(index) = self.widget.as_deref().and_then(panel_index) {
+            chunks[index] = area;
+            return;
+        }
+        let direction = match self.split {
+            Some(Split::Horizontal) => Direction::Horizontal,
+            Some(Split::Vertical) => Direction::Vertical,
+            None => return,
+        };
+        let constraints: Vec<Constraint> = self
+            .children
+            .iter()
+            .map(|child| child.constraint().unwrap_or(Constraint::Min(0)))
+            .collect();
+        let areas = Layout::default()
+            .direction(direction)
+            .margin(0)
+            .constraints(constraints)
+            .split(area);
+        for (child, area) in self.children.iter().zip(areas.iter()) {
+            child.split(*area, chunks);
+        }
+    }
+}
+
+fn panel_index(widget: &str) -> Option<usize> {
+    PANELS
+        .iter()
+        .find(|(name, _)| *name == widget)
+        .map(|(_, index)| *index)
+}
+
+pub fn load(config: &Config) -> (Vec<(String, Option<LayoutNode>)>, Vec<String>) {
+    let mut problems = Vec::new();
+    let builtin: BTreeMap<String, LayoutNode> =
+        toml::from_str(BUILTIN_LAYOUTS).expect("ERROR: invalid built-in layouts");
+    let mut layouts = vec![(DEFAULT_LAYOUT.to_string(), None)];
+    for (name, node) in builtin.into_iter().chain(config.layouts.clone()) {
+        if name == DEFAULT_LAYOUT {
+            problems.push(format!("layout {} is built in and cannot be redefined", name));
+            continue;
+        }
+        if let Err(e) = node.validate() {
+            problems.push(format!("layout {}: {}", name, e));
+            continue;
+        }
+        match layouts.iter_mut().find(|(existing, _)| *existing == name) {
+            Some(existing) => existing.1 = Some(node),
+            None => layouts.push((name, Some(node))),
+        }
+    }
+    (layouts, problems)
+}
+
+pub fn saved_layout() -> Option<String> {
+    let path = config_dir()?.join("session.toml");
+    let session: Session = toml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    session.layout
+}
+
+pub fn save_layout(name: &str) -> io::Result<()> {
+    let dir = config_dir().ok_or(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no configuration directory",
+    ))?;
+    fs::create_dir_all(&dir)?;
+    let session = Session {
+        layout: Some(name.to_string()),
+    };
+    let text = toml::to_string(&session).map_err(io::Error::other)?;
+    fs::write(dir.join("session.toml"), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(text: &str) -> LayoutNode {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn validate_rejects_bad_nodes() {
+        assert_eq!(
+            node("widget = \"scope\"").validate(),
+            Err("unknown widget scope".to_string())
+        );
+        assert_eq!(
+            node("widget = \"log\"\nsize = \"120%\"").validate(),
+            Err("invalid size 120%".to_string())
+        );
+        assert_eq!(
+            node("widget = \"log\"\nsize = \"half\"").validate(),
+            Err("invalid size half".to_string())
+        );
+        assert_eq!(
+            node("split = \"vertical\"").validate(),
+            Err("split without children".to_string())
+        );
+        assert!(node("split = \"vertical\"\nchildren = [{ widget = \"log\", size = \"50%\" }]")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn load_refuses_to_redefine_default() {
+        let config = Config::parse(
+            "[layouts.default]\nwidget = \"log\"\n[layouts.bench]\nwidget = \"scope\"\n[layouts.logs]\nwidget = \"log\"",
+        )
+        .unwrap();
+        let (layouts, problems) = load(&config);
+        let names: Vec<&str> = layouts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["default", "firmware", "test", "logs"]);
+        assert!(layouts[0].1.is_none());
+        assert_eq!(
+            problems,
+            [
+                "layout bench: unknown widget scope",
+                "layout default is built in and cannot be redefined",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_places_widgets_in_their_chunks() {
+        let tree = node(
+            "split = \"horizontal\"\nchildren = [{ widget = \"ext\", size = 40 }, { split = \"vertical\", children = [{ widget = \"ssh\", size = \"50%\" }, { widget = \"log\" }] }]",
+        );
+        let mut chunks = vec![Rect::default(); 17];
+        tree.split(Rect::new(0, 0, 100, 20), &mut chunks);
+        assert_eq!(chunks[7], Rect::new(0, 0, 40, 20));
+        assert_eq!(chunks[13], Rect::new(40, 0, 60, 10));
+        assert_eq!(chunks[15], Rect::new(40, 10, 60, 10));
+        assert_eq!(chunks[1], Rect::default());
+        assert_eq!(tree.panels(), [7, 13, 15]);
+    }
+}
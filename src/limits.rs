@@ -0,0 +1,299 @@
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::sspa::{Register, RegisterState, CHANNEL_NAMES};
+
+const GAN_CHANNELS: std::ops::Range<usize> = 4..8;
+
+#[derive(Clone, Deserialize)]
+pub struct LimitRule {
+    pub name: String,
+    pub value: String,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    #[serde(default)]
+    pub hysteresis: f64,
+    #[serde(default)]
+    pub for_s: f64,
+    #[serde(default = "bell")]
+    pub bell: bool,
+    pub command: Option<String>,
+}
+
+fn bell() -> bool {
+    true
+}
+
+pub enum LimitEvent {
+    Tripped(usize, f64),
+    Cleared(usize, f64),
+}
+
+struct Rule {
+    rule: LimitRule,
+    ast: AST,
+    channels: Vec<usize>,
+    pending_since: Option<Instant>,
+    active: bool,
+}
+
+pub struct Limits {
+    engine: Engine,
+    rules: Vec<Rule>,
+}
+
+impl Rule {
+    fn violated(&self, value: f64) -> bool {
+        // once tripped, the value has to come back past the hysteresis band to clear
+        let margin = if self.active {
+            self.rule.hysteresis
+        } else {
+            0.0
+        };
+        self.rule.above.is_some_and(|above| value > above - margin)
+            || self.rule.below.is_some_and(|below| value < below + margin)
+    }
+}
+
+impl Limits {
+    pub fn new(rules: &[LimitRule]) -> (Limits, Vec<String>) {
+        let engine = Engine::new();
+        let mut problems = Vec::new();
+        let mut limits = Limits {
+            engine,
+            rules: Vec::new(),
+        };
+        for rule in rules {
+            if rule.above.is_none() && rule.below.is_none() {
+                problems.push(format!("limit {} needs above or below", rule.name));
+                continue;
+            }
+            let ast = match limits.engine.compile_expression(&rule.value) {
+                Ok(ast) => ast,
+                Err(e) => {
+                    problems.push(format!("limit {}: {}", rule.name, e));
+                    continue;
+                }
+            };
+            if let Err(e) = limits.eval(&ast, &[Register::new(0); 8]) {
+                problems.push(format!("limit {}: {}", rule.name, e));
+                continue;
+            }
+            let channels = (0..CHANNEL_NAMES.len())
+                .filter(|i| {
+                    rule.value.contains(CHANNEL_NAMES[*i])
+                        || rule.value.contains("gan_imbalance") && GAN_CHANNELS.contains(i)
+                })
+                .collect();
+            limits.rules.push(Rule {
+                rule: rule.clone(),
+                ast,
+                channels,
+                pending_since: None,
+                active: false,
+            });
+        }
+        (limits, problems)
+    }
+
+    fn eval(&self, ast: &AST, adc: &[Register; 8]) -> Result<f64, String> {
+        let mut scope = Scope::new();
+        for (name, reg) in CHANNEL_NAMES.iter().zip(adc) {
+            scope.push(*name, reg.value() as f64);
+        }
+        let gan: Vec<f64> = adc[GAN_CHANNELS]
+            .iter()
+            .map(|reg| reg.value() as f64)
+            .collect();
+        let mean = gan.iter().sum::<f64>() / gan.len() as f64;
+        let spread = gan.iter().cloned().fold(f64::MIN, f64::max)
+            - gan.iter().cloned().fold(f64::MAX, f64::min);
+        let imbalance = if mean > 0.0 {
+            spread / mean * 100.0
+        } else {
+            0.0
+        };
+        scope.push("gan_imbalance", imbalance);
+        let value: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, ast)
+            .map_err(|e| e.to_string())?;
+        value
+            .as_float()
+            .or_else(|_| value.as_int().map(|value| value as f64))
+            .or_else(|_| value.as_bool().map(|value| value as u8 as f64))
+            .map_err(|kind| format!("expression returned {}", kind))
+    }
+
+    pub fn evaluate(&mut self, adc: &[Register; 8]) -> Vec<LimitEvent> {
+        self.evaluate_at(adc, Instant::now())
+    }
+
+    fn evaluate_at(&mut self, adc: &[Register; 8], now: Instant) -> Vec<LimitEvent> {
+        let mut events = Vec::new();
+        for i in 0..self.rules.len() {
+            // a corrupted word says nothing about the measurement, keep the rule where it was
+            let corrupted = self.rules[i]
+                .channels
+                .iter()
+                .any(|channel| adc[*channel].state() == RegisterState::ParityError);
+            if corrupted {
+                continue;
+            }
+            let value = match self.eval(&self.rules[i].ast, adc) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let rule = &mut self.rules[i];
+            let debounce = Duration::from_secs_f64(rule.rule.for_s.max(0.0));
+            match (rule.violated(value), rule.active) {
+                (true, false) => {
+                    let since = *rule.pending_since.get_or_insert(now);
+                    if now.duration_since(since) >= debounce {
+                        rule.active = true;
+                        rule.pending_since = None;
+                        events.push(LimitEvent::Tripped(i, value));
+                    }
+                }
+                (false, true) => {
+                    rule.active = false;
+                    events.push(LimitEvent::Cleared(i, value));
+                }
+                (false, false) => rule.pending_since = None,
+                (true, true) => {}
+            }
+        }
+        events
+    }
+
+    pub fn rule(&self, i: usize) -> &LimitRule {
+        &self.rules[i].rule
+    }
+
+    pub fn active(&self) -> usize {
+        self.rules.iter().filter(|rule| rule.active).count()
+    }
+
+    pub fn violated_channels(&self) -> [bool; 8] {
+        let mut channels = [false; 8];
+        for rule in self.rules.iter().filter(|rule| rule.active) {
+            for i in &rule.channels {
+                channels[*i] = true;
+            }
+        }
+        channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(value: &str, above: f64, hysteresis: f64, for_s: f64) -> LimitRule {
+        LimitRule {
+            name: "hot".to_string(),
+            value: value.to_string(),
+            above: Some(above),
+            below: None,
+            hysteresis,
+            for_s,
+            bell: false,
+            command: None,
+        }
+    }
+
+    fn adc(values: [u16; 8]) -> [Register; 8] {
+        values.map(|value| Register::new(Register::encode(value)))
+    }
+
+    fn temperature(value: u16) -> [Register; 8] {
+        adc([0, 0, 0, value, 0, 0, 0, 0])
+    }
+
+    fn events(limits: &mut Limits, adc: [Register; 8], now: Instant) -> Vec<(bool, f64)> {
+        limits
+            .evaluate_at(&adc, now)
+            .into_iter()
+            .map(|event| match event {
+                LimitEvent::Tripped(_, value) => (true, value),
+                LimitEvent::Cleared(_, value) => (false, value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trips_once_the_violation_lasts_for_s() {
+        let (mut limits, _) = Limits::new(&[rule("temperature", 50.0, 0.0, 1.0)]);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(events(&mut limits, temperature(60), at(0)).is_empty());
+        assert!(events(&mut limits, temperature(60), at(500)).is_empty());
+        assert_eq!(
+            events(&mut limits, temperature(61), at(1000)),
+            [(true, 61.0)]
+        );
+        assert!(events(&mut limits, temperature(62), at(1500)).is_empty());
+        assert_eq!(limits.active(), 1);
+    }
+
+    #[test]
+    fn dip_restarts_the_debounce() {
+        let (mut limits, _) = Limits::new(&[rule("temperature", 50.0, 0.0, 1.0)]);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(events(&mut limits, temperature(60), at(0)).is_empty());
+        assert!(events(&mut limits, temperature(40), at(500)).is_empty());
+        assert!(events(&mut limits, temperature(60), at(1000)).is_empty());
+        assert_eq!(
+            events(&mut limits, temperature(60), at(2000)),
+            [(true, 60.0)]
+        );
+    }
+
+    #[test]
+    fn clears_only_past_the_hysteresis_band() {
+        let (mut limits, _) = Limits::new(&[rule("temperature", 50.0, 5.0, 0.0)]);
+        let now = Instant::now();
+        assert_eq!(events(&mut limits, temperature(60), now), [(true, 60.0)]);
+        assert!(events(&mut limits, temperature(48), now).is_empty());
+        assert_eq!(events(&mut limits, temperature(44), now), [(false, 44.0)]);
+        assert!(events(&mut limits, temperature(48), now).is_empty());
+        assert_eq!(limits.active(), 0);
+    }
+
+    #[test]
+    fn parity_error_keeps_the_rule_where_it_was() {
+        let (mut limits, _) = Limits::new(&[rule("temperature", 50.0, 0.0, 0.0)]);
+        let now = Instant::now();
+        assert_eq!(events(&mut limits, temperature(60), now), [(true, 60.0)]);
+        let mut corrupted = temperature(0);
+        corrupted[3] = Register::new(Register::encode(10) ^ 0x0001);
+        assert!(events(&mut limits, corrupted, now).is_empty());
+        assert!(limits.violated_channels()[3]);
+    }
+
+    #[test]
+    fn gan_imbalance_is_the_spread_over_the_mean() {
+        let (mut limits, _) = Limits::new(&[rule("gan_imbalance", 20.0, 0.0, 0.0)]);
+        let now = Instant::now();
+        assert!(events(&mut limits, adc([0, 0, 0, 0, 100, 100, 110, 90]), now).is_empty());
+        assert_eq!(
+            events(&mut limits, adc([0, 0, 0, 0, 100, 100, 125, 75]), now),
+            [(true, 50.0)]
+        );
+        assert!(limits.violated_channels()[4..]
+            .iter()
+            .all(|channel| *channel));
+    }
+
+    #[test]
+    fn reports_invalid_rules() {
+        let mut open = rule("temperature", 0.0, 0.0, 0.0);
+        open.above = None;
+        let (limits, problems) = Limits::new(&[open, rule("temperature +", 50.0, 0.0, 0.0)]);
+        assert_eq!(limits.active(), 0);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0], "limit hot needs above or below");
+    }
+}
@@ -1,29 +1,83 @@
+mod api;
+mod audit;
+mod backup;
+mod cli;
 mod color;
 mod events;
 mod sspa;
 mod ui;
 mod state;
 mod launcher;
+mod connection;
+mod telemetry;
+mod config;
+mod diagnostics;
+mod scrollback;
+mod snapshot;
+mod script;
+mod action;
+mod device;
+mod keymap;
+mod palette;
+mod plan;
+mod layout;
+mod limits;
+mod metrics;
+mod theme;
 
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
+    style::Print,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use events::Events;
 use state::StateKeeper;
 
-use std::io;
+use std::{
+    env, io, process,
+    time::{Duration, Instant},
+};
+use tokio::time::{interval, sleep};
 
-use tui::{backend::CrosstermBackend, Terminal};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    Terminal,
+};
 
 use ui::ui;
 
-use launcher::Launcher;
+use config::Config;
 
 
 #[tokio::main]
 async fn main() -> Result<(), io::Error> {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("ERROR: invalid configuration, refusing to start: {}", e);
+            process::exit(cli::EXIT_USAGE);
+        }
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    match cli::parse(&args) {
+        Ok(args) => {
+            config.read_only |= args.read_only;
+            if let Some(cli) = args.command {
+                process::exit(cli::run(cli, &config).await);
+            }
+        }
+        Err(e) if e.is_empty() => {
+            println!("{}", cli::usage());
+            process::exit(cli::EXIT_OK);
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            eprintln!("{}", cli::usage());
+            process::exit(cli::EXIT_USAGE);
+        }
+    }
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -31,21 +85,11 @@ async fn main() -> Result<(), io::Error> {
     let mut terminal = Terminal::new(backend)?;
 
     let mut events = Events::new();
-    let mut state = StateKeeper::new();
-    let mut term = Launcher::new(47);
-    term.launch("ping localhost");
-    let mut ssh = Launcher::new(20);
-    ssh.launch("ssh -tt dietpi@192.168.1.16 sspa -v -H -M");
-    //ssh.launch("ssh -tt dietpi@192.168.1.16 ping 192.168.1.15");
+    let mut state = StateKeeper::new(&config);
+    state.launch_terminal("ping localhost");
+    event_loop(&mut terminal, &mut events, &mut state, &config).await?;
 
-    loop {
-        if events.hanlde(&mut state) {
-            break;
-        }
-        terminal.draw(|f| {
-            ui(f, &mut state, &mut term, &mut ssh);
-        })?;
-    }
+    state.close_writes();
 
     // restore terminal
     disable_raw_mode()?;
@@ -56,5 +100,141 @@ async fn main() -> Result<(), io::Error> {
     )?;
     terminal.show_cursor()?;
 
+    if let Err(e) = layout::save_layout(state.layout_name()) {
+        eprintln!("ERROR: failed to save session: {}", e);
+    }
+
     Ok(())
 }
+
+// redraws at most max_fps times a second, and only after something changed
+async fn event_loop<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    events: &mut Events,
+    state: &mut StateKeeper,
+    config: &Config,
+) -> io::Result<()> {
+    let wakeup = state.wakeup();
+    let mut tick = interval(Duration::from_millis(config.tick_ms.max(1)));
+    let frame = Duration::from_secs(1) / config.max_fps.max(1);
+    let mut last_draw: Option<Instant> = None;
+    let mut dirty = true;
+
+    loop {
+        let frame_wait = last_draw.map_or(Duration::ZERO, |last| frame.saturating_sub(last.elapsed()));
+        if dirty && frame_wait.is_zero() {
+            terminal.draw(|f| {
+                ui(f, state);
+            })?;
+            last_draw = Some(Instant::now());
+            dirty = false;
+        }
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    if events.handle(event, state) {
+                        break;
+                    }
+                    dirty = true;
+                }
+                None => break,
+            },
+            _ = wakeup.notified() => {}
+            _ = tick.tick() => {}
+            _ = sleep(frame_wait), if dirty => {}
+        }
+        dirty |= state.poll();
+        if state.take_bell() {
+            execute!(terminal.backend_mut(), Print("\x07"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use std::{
+        env, fs,
+        sync::{Arc, Mutex},
+    };
+    use tokio::time::timeout;
+    use tui::{layout::Rect, TerminalOptions, Viewport};
+
+    #[derive(Clone, Default)]
+    struct Screen(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Screen {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Screen {
+        fn take(&self) -> String {
+            String::from_utf8_lossy(&std::mem::take(&mut *self.0.lock().unwrap())).into_owned()
+        }
+    }
+
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    // a session that never starts the link, so nothing but the fed events wakes the loop
+    async fn run(keys: &[char], close: bool) -> (StateKeeper, String) {
+        let name = format!("sspa_tui_{}_{}_loop_audit.log", process::id(), keys.len());
+        let audit_file = env::temp_dir().join(name);
+        let config = Config {
+            audit_file: Some(audit_file.clone()),
+            ..Config::default()
+        };
+        let mut state = StateKeeper::headless(&config);
+        state.dispatch(action::Action::Disconnect);
+        let screen = Screen::default();
+        let options = TerminalOptions {
+            viewport: Viewport::fixed(Rect::new(0, 0, 200, 50)),
+        };
+        let mut terminal =
+            Terminal::with_options(CrosstermBackend::new(screen.clone()), options).unwrap();
+        let (tx, mut events) = Events::channel();
+        for c in keys {
+            tx.send(key(*c)).await.unwrap();
+        }
+        if close {
+            drop(tx);
+        }
+        let result = timeout(
+            Duration::from_secs(5),
+            event_loop(&mut terminal, &mut events, &mut state, &config),
+        )
+        .await;
+        let _ = fs::remove_file(&audit_file);
+        assert!(result.expect("event loop did not return").is_ok());
+        (state, screen.take())
+    }
+
+    #[tokio::test]
+    async fn quit_key_ends_the_loop_after_the_first_frame() {
+        let (state, screen) = run(&['q'], false).await;
+        assert!(screen.contains("Registers"));
+        assert!(!state.help());
+    }
+
+    #[tokio::test]
+    async fn open_help_takes_the_quit_key() {
+        let (state, _) = run(&['?', 'q'], true).await;
+        assert!(!state.help());
+    }
+
+    #[tokio::test]
+    async fn closed_event_stream_ends_the_loop() {
+        let (state, _) = run(&['?'], true).await;
+        assert!(state.help());
+    }
+}
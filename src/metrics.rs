@@ -0,0 +1,193 @@
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::diagnostics::DiagnosticsSender;
+
+const MAX_REQUEST: usize = 8 * 1024;
+
+pub struct Metrics {
+    text: Arc<Mutex<String>>,
+    task: JoinHandle<()>,
+}
+
+impl Metrics {
+    pub fn new(port: u16, diagnostics: DiagnosticsSender) -> Metrics {
+        let text = Arc::new(Mutex::new(String::new()));
+        let shared = text.clone();
+        let task = tokio::spawn(async move {
+            let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    diagnostics.error(
+                        "metrics",
+                        format!("failed to listen on port {}: {}", port, e),
+                    );
+                    return;
+                }
+            };
+            diagnostics.info(
+                "metrics",
+                format!("serving http://127.0.0.1:{}/metrics", port),
+            );
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve(stream, shared.clone()));
+                    }
+                    Err(e) => {
+                        diagnostics.warning("metrics", format!("accept returned error: {}", e))
+                    }
+                }
+            }
+        });
+        Metrics { text, task }
+    }
+
+    pub fn publish(&self, text: String) {
+        *self.text.lock().expect("ERROR: metrics lock poisoned") = text;
+    }
+}
+
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(mut stream: TcpStream, text: Arc<Mutex<String>>) {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+    let response = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = text.lock().expect("ERROR: metrics lock poisoned").clone();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        (Some("GET"), Some(_)) => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string(),
+    };
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+pub struct Exposition {
+    text: String,
+    family: &'static str,
+}
+
+impl Exposition {
+    pub fn new() -> Exposition {
+        Exposition {
+            text: String::new(),
+            family: "",
+        }
+    }
+
+    pub fn family(&mut self, name: &'static str, kind: &str, help: &str) {
+        self.family = name;
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, labels: &[(&str, &str)], value: impl Into<f64>) {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        let value = value.into();
+        let _ = match labels.is_empty() {
+            true => writeln!(self.text, "{} {}", self.family, value),
+            false => writeln!(
+                self.text,
+                "{}{{{}}} {}",
+                self.family,
+                labels.join(","),
+                value
+            ),
+        };
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(path: &str, body: &str) -> String {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let text = Arc::new(Mutex::new(body.to_string()));
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream, text).await;
+        });
+        let mut client = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap();
+        response
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let mut out = Exposition::new();
+        out.family("sspa_adc", "gauge", "ADC measurement per channel.");
+        out.sample(&[("channel", "a\"b\\c\nd"), ("unit", "mA")], 12);
+        out.sample(&[], 1.5);
+        assert_eq!(
+            out.finish(),
+            "# HELP sspa_adc ADC measurement per channel.\n\
+             # TYPE sspa_adc gauge\n\
+             sspa_adc{channel=\"a\\\"b\\\\c\\nd\",unit=\"mA\"} 12\n\
+             sspa_adc 1.5\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served_as_text_exposition() {
+        let response = get("/metrics", "sspa_version 3\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nContent-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("\r\nContent-Length: 15\r\n"));
+        assert!(response.ends_with("\r\n\r\nsspa_version 3\n"));
+    }
+
+    #[tokio::test]
+    async fn other_paths_are_not_found() {
+        let response = get("/", "sspa_version 3\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{
+    action::{Action, CompileStep, ControlCommand},
+    state::{EditTarget, WIDGETS},
+    theme::THEMES,
+    sspa::{CHANNEL_NAMES, PROTECTION_NAMES, THRESHOLD_NAMES},
+    ui::{WidgetId, PANES},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Arg {
+    None,
+    Value,
+    Path,
+    File,
+}
+
+impl Arg {
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Arg::None => "",
+            Arg::Value => "<value>",
+            Arg::Path => "[path]",
+            Arg::File => "<file>",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum CommandKind {
+    Action(Action),
+    Write(EditTarget),
+    Focus(WidgetId),
+    Export(WidgetId),
+    Script,
+    TestPlan,
+    Snapshot,
+    SnapshotDiff,
+    Backup,
+    Restore,
+    AuditExport,
+}
+
+#[derive(Clone)]
+pub struct Command {
+    pub name: String,
+    pub arg: Arg,
+    pub kind: CommandKind,
+}
+
+impl Command {
+    fn new(name: impl Into<String>, arg: Arg, kind: CommandKind) -> Command {
+        Command {
+            name: name.into(),
+            arg,
+            kind,
+        }
+    }
+
+    fn action(name: impl Into<String>, action: Action) -> Command {
+        Command::new(name, Arg::None, CommandKind::Action(action))
+    }
+}
+
+pub const CONTROL_COMMANDS: [(&str, ControlCommand); 5] = [
+    ("store_nvm", ControlCommand::StoreNvm),
+    ("load_nvm", ControlCommand::LoadNvm),
+    ("alarms_reset", ControlCommand::AlarmsReset),
+    ("sspa_reset", ControlCommand::SspaReset),
+    ("sspa_disable", ControlCommand::SspaDisable),
+];
+
+const COMPILE_STEPS: [(&str, CompileStep); 5] = [
+    ("build", CompileStep::Build),
+    ("clean_build", CompileStep::CleanBuild),
+    ("clean_build_flash", CompileStep::CleanBuildFlash),
+    ("build_flash", CompileStep::BuildFlash),
+    ("flash", CompileStep::Flash),
+];
+
+const PANE_TOGGLES: [(&str, Action); 5] = [
+    ("follow", Action::ToggleFollow),
+    ("timestamps", Action::ToggleTimestamps),
+    ("filter", Action::CycleFilter),
+    ("hex", Action::ToggleHex),
+    ("top", Action::ScrollTop),
+];
+
+const TNR_FIELDS: [&str; 3] = ["period", "pulse_width", "count"];
+
+pub fn slug(wid: WidgetId) -> String {
+    wid.name().to_lowercase().replace(' ', "_")
+}
+
+pub fn commands(presets: &[String], layouts: &[String]) -> Vec<Command> {
+    let mut commands = Vec::new();
+    for (i, name) in THRESHOLD_NAMES[..9].iter().enumerate() {
+        commands.push(Command::new(
+            format!("set threshold.{}", name),
+            Arg::Value,
+            CommandKind::Write(EditTarget::Threshold(i)),
+        ));
+    }
+    for (i, name) in CHANNEL_NAMES.iter().enumerate() {
+        commands.push(Command::new(
+            format!("set dac.{}", name),
+            Arg::Value,
+            CommandKind::Write(EditTarget::Dac(i)),
+        ));
+    }
+    for (i, name) in CHANNEL_NAMES.iter().enumerate() {
+        commands.push(Command::new(
+            format!("set offset.{}", name),
+            Arg::Value,
+            CommandKind::Write(EditTarget::Offset(i)),
+        ));
+    }
+    for (i, name) in TNR_FIELDS.iter().enumerate() {
+        commands.push(Command::new(
+            format!("set tnr.{}", name),
+            Arg::Value,
+            CommandKind::Write(EditTarget::Tnr(i)),
+        ));
+    }
+    commands.push(Command::action("dac clear", Action::ClearDac));
+    for (i, name) in presets.iter().enumerate() {
+        commands.push(Command::action(
+            format!("preset load {}", name),
+            Action::LoadPreset(i),
+        ));
+    }
+    commands.push(Command::action("preset save", Action::TnrSave));
+    commands.push(Command::action("tnr launch", Action::TnrLaunch));
+    commands.push(Command::action("tnr stop", Action::TnrStop));
+    commands.push(Command::action("power toggle", Action::TogglePowerEnable));
+    for (name, command) in CONTROL_COMMANDS {
+        commands.push(Command::action(
+            format!("control {}", name),
+            Action::Control(command),
+        ));
+    }
+    for (i, name) in PROTECTION_NAMES.iter().enumerate() {
+        commands.push(Command::action(
+            format!("protection toggle {}", name),
+            Action::ToggleProtection(i),
+        ));
+    }
+    commands.push(Command::action("hard reset", Action::HardReset));
+    for (name, step) in COMPILE_STEPS {
+        commands.push(Command::action(format!("compile {}", name), Action::Compile(step)));
+    }
+    for (name, action) in PANE_TOGGLES {
+        commands.push(Command::action(format!("pane {}", name), action));
+    }
+    for wid in WIDGETS {
+        commands.push(Command::new(
+            format!("focus {}", slug(wid)),
+            Arg::None,
+            CommandKind::Focus(wid),
+        ));
+    }
+    for wid in PANES {
+        commands.push(Command::new(
+            format!("export {}", slug(wid)),
+            Arg::Path,
+            CommandKind::Export(wid),
+        ));
+    }
+    commands.push(Command::new("script run", Arg::File, CommandKind::Script));
+    commands.push(Command::action("script abort", Action::AbortScript));
+    commands.push(Command::new("test run", Arg::File, CommandKind::TestPlan));
+    commands.push(Command::new("snapshot save", Arg::Path, CommandKind::Snapshot));
+    commands.push(Command::new("snapshot diff", Arg::File, CommandKind::SnapshotDiff));
+    commands.push(Command::new("config backup", Arg::Path, CommandKind::Backup));
+    commands.push(Command::new("config restore", Arg::File, CommandKind::Restore));
+    commands.push(Command::new("audit export", Arg::Path, CommandKind::AuditExport));
+    commands.push(Command::action("connect", Action::Connect));
+    commands.push(Command::action("disconnect", Action::Disconnect));
+    commands.push(Command::action("zoom", Action::ToggleZoom));
+    for (name, theme) in THEMES {
+        commands.push(Command::action(format!("theme {}", name), Action::SelectTheme(theme)));
+    }
+    for (i, name) in layouts.iter().enumerate() {
+        commands.push(Command::action(format!("layout {}", name), Action::SelectLayout(i)));
+    }
+    commands.push(Command::action("help", Action::Help));
+    commands
+}
+
+pub fn matches(commands: &[Command], input: &str) -> Vec<(usize, Option<String>)> {
+    let input = input.trim();
+    let mut scored = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        let mut best = fuzzy_score(input, &command.name).map(|score| (score, None));
+        if command.arg != Arg::None {
+            if let Some((head, arg)) = input.rsplit_once(' ') {
+                if let Some(score) = fuzzy_score(head, &command.name) {
+                    if best.as_ref().is_none_or(|(best, _)| score >= *best) {
+                        best = Some((score, Some(arg.to_string())));
+                    }
+                }
+            }
+        }
+        if let Some((score, arg)) = best {
+            scored.push((score, i, arg));
+        }
+    }
+    scored.sort_by_key(|(score, i, _)| (-score, *i));
+    scored.into_iter().map(|(_, i, arg)| (i, arg)).collect()
+}
+
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in pattern.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|t| *t == c)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || matches!(text[found - 1], ' ' | '.' | '_') {
+            score += 3;
+        }
+        score -= (found - position).min(5) as i64;
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(input: &str) -> Option<(String, Option<String>)> {
+        let commands = commands(&["bench".to_string()], &["default".to_string()]);
+        let (i, arg) = matches(&commands, input).into_iter().next()?;
+        Some((commands[i].name.clone(), arg))
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(fuzzy_score("hrst", "hard reset").is_some());
+        assert!(fuzzy_score("tsrh", "hard reset").is_none());
+        assert!(fuzzy_score("", "hard reset").is_some());
+    }
+
+    #[test]
+    fn contiguous_and_word_start_matches_rank_first() {
+        assert!(fuzzy_score("stop", "tnr stop") > fuzzy_score("stop", "protection toggle"));
+        assert_eq!(best("connect").unwrap().0, "connect");
+        assert_eq!(best("hard").unwrap().0, "hard reset");
+        assert_eq!(best("tnr st").unwrap().0, "tnr stop");
+    }
+
+    #[test]
+    fn trailing_word_becomes_the_argument() {
+        let (name, arg) = best("set dac.gan_1 77").unwrap();
+        assert_eq!(name, "set dac.gan_1_current");
+        assert_eq!(arg.as_deref(), Some("77"));
+        assert_eq!(best("preset load bench"), Some(("preset load bench".to_string(), None)));
+    }
+
+    #[test]
+    fn unmatched_input_has_no_results() {
+        assert!(best("qqqq").is_none());
+    }
+
+    #[test]
+    fn every_pane_can_be_exported() {
+        let commands = commands(&[], &[]);
+        for wid in PANES {
+            let name = format!("export {}", slug(wid));
+            assert!(commands.iter().any(|command| command.name == name), "{name}");
+        }
+    }
+}
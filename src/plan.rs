@@ -0,0 +1,483 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::{config_dir, host_name, user_name},
+    script::Bridge,
+};
+
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+pub struct Plan {
+    name: String,
+    report_dir: Option<PathBuf>,
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Step {
+    name: String,
+    writes: Vec<Write>,
+    preset: Option<String>,
+    tnr: Option<[u16; 3]>,
+    tnr_stop: bool,
+    control: Vec<String>,
+    wait_ms: u64,
+    checks: Vec<Check>,
+    no_trips: bool,
+    ignore_bits: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Write {
+    group: String,
+    name: String,
+    value: u16,
+}
+
+#[derive(Deserialize)]
+struct Check {
+    #[serde(default = "adc")]
+    group: String,
+    name: String,
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+fn adc() -> String {
+    "adc".to_string()
+}
+
+#[derive(Serialize)]
+struct Measurement {
+    name: String,
+    value: i64,
+    limit: String,
+    pass: bool,
+}
+
+#[derive(Serialize)]
+struct StepResult {
+    name: String,
+    pass: bool,
+    measurements: Vec<Measurement>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    plan: String,
+    serial_number: i64,
+    operator: String,
+    host: String,
+    started: String,
+    finished: String,
+    verdict: String,
+    steps: Vec<StepResult>,
+    signed_off_by: Option<String>,
+    signed_off_at: Option<String>,
+    // report files without their extension
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Plan, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let plan: Plan = toml::from_str(&text).map_err(|e| e.to_string())?;
+        if plan.steps.is_empty() {
+            return Err("plan has no steps".to_string());
+        }
+        Ok(plan)
+    }
+
+    // the report is written unsigned, the operator signs off the verdict afterwards
+    pub fn run(self, bridge: &Bridge) -> Result<Report, String> {
+        let started = Local::now();
+        let serial_number = bridge.read_retry("threshold", "serial_number", SERIAL_TIMEOUT)?;
+        bridge.log(&format!("{} on unit {}", self.name, serial_number));
+        let mut steps = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let result = step.run(bridge);
+            let verdict = if result.pass { "PASS" } else { "FAIL" };
+            bridge.log(&format!(
+                "step {}/{} {}: {}",
+                i + 1,
+                self.steps.len(),
+                step.name,
+                verdict
+            ));
+            for measurement in result.measurements.iter().filter(|m| !m.pass) {
+                bridge.log(&format!(
+                    "    {} = {} outside {}",
+                    measurement.name, measurement.value, measurement.limit
+                ));
+            }
+            if let Some(error) = &result.error {
+                bridge.log(&format!("    {}", error));
+            }
+            let aborted = result.aborted();
+            steps.push(result);
+            if aborted {
+                break;
+            }
+        }
+        let dir = self
+            .report_dir
+            .clone()
+            .or_else(|| config_dir().map(|dir| dir.join("reports")))
+            .unwrap_or_else(|| PathBuf::from("."));
+        let stem = format!("{}-{}", serial_number, started.format("%Y%m%d-%H%M%S"));
+        let report = Report {
+            plan: self.name.clone(),
+            serial_number,
+            operator: user_name(),
+            host: host_name(),
+            started: started.to_rfc3339(),
+            finished: Local::now().to_rfc3339(),
+            verdict: verdict(&steps).to_string(),
+            steps,
+            signed_off_by: None,
+            signed_off_at: None,
+            path: dir.join(stem),
+        };
+        match report.write() {
+            Ok(path) => bridge.log(&format!("report written to {}", path.display())),
+            Err(e) => return Err(format!("failed to write report: {}", e)),
+        }
+        Ok(report)
+    }
+}
+
+fn verdict(steps: &[StepResult]) -> &'static str {
+    match steps.last() {
+        Some(step) if step.aborted() => "ABORTED",
+        _ if steps.iter().all(|step| step.pass) => "PASS",
+        _ => "FAIL",
+    }
+}
+
+impl Step {
+    fn run(&self, bridge: &Bridge) -> StepResult {
+        let mut measurements = Vec::new();
+        let error = self.execute(bridge, &mut measurements).err();
+        StepResult {
+            name: self.name.clone(),
+            pass: error.is_none() && measurements.iter().all(|m| m.pass),
+            measurements,
+            error,
+        }
+    }
+
+    fn execute(&self, bridge: &Bridge, measurements: &mut Vec<Measurement>) -> Result<(), String> {
+        for write in &self.writes {
+            bridge.write(&write.group, &write.name, write.value as i64)?;
+        }
+        if let Some(preset) = &self.preset {
+            bridge.call("preset_load", json!({ "name": preset }))?;
+        }
+        for command in &self.control {
+            bridge.call("control", json!({ "command": command }))?;
+        }
+        if let Some([period, pulse_width, count]) = self.tnr {
+            let params = json!({ "period": period, "pulse_width": pulse_width, "count": count });
+            bridge.call("tnr_launch", params)?;
+        }
+        bridge.sleep(self.wait_ms as i64)?;
+        if self.tnr_stop {
+            bridge.call("tnr_stop", Value::Null)?;
+        }
+        for check in &self.checks {
+            let value = bridge.read(&check.group, &check.name)?;
+            let limit = match (check.min, check.max) {
+                (Some(min), Some(max)) => format!("[{}, {}]", min, max),
+                (Some(min), None) => format!(">= {}", min),
+                (None, Some(max)) => format!("<= {}", max),
+                (None, None) => "any".to_string(),
+            };
+            measurements.push(Measurement {
+                name: format!("{}.{}", check.group, check.name),
+                value,
+                limit,
+                pass: check.min.is_none_or(|min| value >= min)
+                    && check.max.is_none_or(|max| value <= max),
+            });
+        }
+        if self.no_trips {
+            for bit in bridge.status_bits()? {
+                if bit == "SSPA_Active" || self.ignore_bits.contains(&bit) {
+                    continue;
+                }
+                measurements.push(Measurement {
+                    name: format!("status.{}", bit),
+                    value: 1,
+                    limit: "not tripped".to_string(),
+                    pass: false,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StepResult {
+    fn aborted(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|error| error == "aborted" || error.ends_with("session closed"))
+    }
+}
+
+impl Report {
+    pub fn plan(&self) -> &str {
+        &self.plan
+    }
+
+    pub fn serial_number(&self) -> i64 {
+        self.serial_number
+    }
+
+    pub fn verdict(&self) -> &str {
+        &self.verdict
+    }
+
+    pub fn outcome(&self) -> Result<(), String> {
+        let failed = self.steps.iter().filter(|step| !step.pass).count();
+        match self.steps.last() {
+            Some(step) if step.aborted() => Err(step.error.clone().unwrap_or_default()),
+            _ if failed == 0 => Ok(()),
+            _ => Err(format!("{} of {} steps failed", failed, self.steps.len())),
+        }
+    }
+
+    pub fn sign(&mut self, operator: String) -> Result<PathBuf, String> {
+        self.signed_off_by = Some(operator);
+        self.signed_off_at = Some(Local::now().to_rfc3339());
+        self.write()
+    }
+
+    fn write(&self) -> Result<PathBuf, String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(self.path.with_extension("json"), json).map_err(|e| e.to_string())?;
+        let html = self.path.with_extension("html");
+        fs::write(&html, self.html()).map_err(|e| e.to_string())?;
+        Ok(html)
+    }
+
+    fn html(&self) -> String {
+        let mut rows = String::new();
+        for step in &self.steps {
+            let verdict = if step.pass { "PASS" } else { "FAIL" };
+            let mut details: Vec<String> = step
+                .measurements
+                .iter()
+                .map(|m| {
+                    format!(
+                        "{} = {} ({}) {}",
+                        escape(&m.name),
+                        m.value,
+                        escape(&m.limit),
+                        if m.pass { "ok" } else { "out of limits" }
+                    )
+                })
+                .collect();
+            details.extend(step.error.iter().map(|e| escape(e)));
+            rows.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                verdict.to_lowercase(),
+                escape(&step.name),
+                verdict,
+                details.join("<br>")
+            ));
+        }
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{plan} - unit {serial}</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #999; padding: 4px 8px; text-align: left; }}
+.pass {{ background: #dfd; }}
+.fail {{ background: #fdd; }}
+</style>
+</head>
+<body>
+<h1>{plan}</h1>
+<p>Unit serial number: {serial}<br>
+Operator: {operator}<br>
+Host: {host}<br>
+Started: {started}<br>
+Finished: {finished}</p>
+<h2>Verdict: {verdict}</h2>
+<p>{sign_off}</p>
+<table>
+<tr><th>Step</th><th>Result</th><th>Details</th></tr>
+{rows}</table>
+</body>
+</html>
+",
+            plan = escape(&self.plan),
+            serial = self.serial_number,
+            host = escape(&self.host),
+            started = self.started,
+            finished = self.finished,
+            verdict = self.verdict,
+            rows = rows,
+            operator = escape(&self.operator),
+            sign_off = match (&self.signed_off_by, &self.signed_off_at) {
+                (Some(by), Some(at)) => format!("Signed off by {} at {}", escape(by), at),
+                _ => "Not signed off".to_string(),
+            },
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{Message, Script};
+    use std::{
+        env, process,
+        sync::{Arc, Mutex},
+        thread,
+    };
+    use tokio::sync::Notify;
+
+    fn step(pass: bool, error: Option<&str>) -> StepResult {
+        StepResult {
+            name: "step".to_string(),
+            pass,
+            measurements: Vec::new(),
+            error: error.map(str::to_string),
+        }
+    }
+
+    // runs the plan against a unit that reports fixed values and accepts every write
+    fn run(plan: &str) -> (Report, Result<(), String>, Vec<String>) {
+        let plan: Plan = toml::from_str(plan).unwrap();
+        let finished = Arc::new(Mutex::new(None));
+        let slot = finished.clone();
+        let path = Path::new("plan.toml");
+        let mut script = Script::spawn(
+            "test",
+            path,
+            false,
+            Arc::new(Notify::new()),
+            move |bridge| {
+                let report = plan.run(bridge)?;
+                let outcome = report.outcome();
+                *slot.lock().unwrap() = Some(report);
+                outcome
+            },
+        );
+        let mut calls = Vec::new();
+        loop {
+            for message in script.poll() {
+                match message {
+                    Message::Request(request) => {
+                        calls.push(request.method.clone());
+                        let result = match request.method.as_str() {
+                            "get" => json!([
+                                { "name": "serial_number", "value": 1234, "parity": "ok" },
+                                { "name": "output_power", "value": 15, "parity": "ok" },
+                                { "name": "drive_level", "value": 7, "parity": "ok" },
+                            ]),
+                            _ => Value::Bool(true),
+                        };
+                        request.respond(Ok(result));
+                    }
+                    Message::Log(_) => {}
+                    Message::Finished(result) => {
+                        let report = finished.lock().unwrap().take().unwrap();
+                        return (report, result, calls);
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn runner_checks_limits_and_writes_an_unsigned_report() {
+        let dir = env::temp_dir().join(format!("sspa_tui_{}_reports", process::id()));
+        let plan = format!(
+            r#"
+            name = "bench"
+            report_dir = "{}"
+            [[steps]]
+            name = "bias"
+            writes = [{{ group = "dac", name = "gan_1_current", value = 5 }}]
+            checks = [{{ name = "output_power", min = 10, max = 20 }}]
+            [[steps]]
+            name = "drive"
+            checks = [{{ name = "drive_level", max = 3 }}]
+            "#,
+            dir.display()
+        );
+        let (mut report, result, calls) = run(&plan);
+        assert_eq!(result, Err("1 of 2 steps failed".to_string()));
+        assert_eq!(calls, ["get", "write", "get", "get"]);
+        assert_eq!(report.serial_number(), 1234);
+        assert_eq!(report.verdict(), "FAIL");
+        assert!(report.steps[0].pass);
+        assert_eq!(report.steps[1].measurements[0].limit, "<= 3");
+        let json = fs::read_to_string(report.path.with_extension("json")).unwrap();
+        assert!(json.contains("\"signed_off_by\": null"));
+
+        let html = report.sign("alice".to_string()).unwrap();
+        let json = fs::read_to_string(report.path.with_extension("json")).unwrap();
+        let html = fs::read_to_string(html).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(json.contains("\"signed_off_by\": \"alice\""));
+        assert!(report.signed_off_at.is_some());
+        assert!(html.contains("Signed off by alice at "));
+    }
+
+    #[test]
+    fn verdict_follows_the_steps() {
+        assert_eq!(verdict(&[step(true, None), step(true, None)]), "PASS");
+        assert_eq!(verdict(&[step(true, None), step(false, None)]), "FAIL");
+        assert_eq!(
+            verdict(&[step(false, Some("write failed: not connected"))]),
+            "FAIL"
+        );
+        assert_eq!(
+            verdict(&[step(false, None), step(false, Some("aborted"))]),
+            "ABORTED"
+        );
+        assert_eq!(
+            verdict(&[step(false, Some("get failed: session closed"))]),
+            "ABORTED"
+        );
+    }
+
+    #[test]
+    fn escape_covers_html_specials() {
+        assert_eq!(
+            escape(r#"<b>"R&D"</b>"#),
+            "&lt;b&gt;&quot;R&amp;D&quot;&lt;/b&gt;"
+        );
+    }
+}
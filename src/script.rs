@@ -0,0 +1,307 @@
+use rhai::{Dynamic, Engine, EvalAltResult, INT};
+use serde_json::{json, Value};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use crate::{
+    api::Request,
+    scrollback::{Line, Stream},
+    sspa::{find_register, STATUS_BITS},
+};
+
+const SLEEP_STEP: Duration = Duration::from_millis(20);
+const READ_RETRY: i64 = 100;
+
+pub enum Message {
+    Request(Request),
+    Log(String),
+    Finished(Result<(), String>),
+}
+
+pub struct Script {
+    name: String,
+    rx: UnboundedReceiver<Message>,
+    abort: Arc<AtomicBool>,
+    started: Instant,
+    seq: u64,
+}
+
+type RhaiResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Clone)]
+pub struct Bridge {
+    source: &'static str,
+    name: String,
+    tx: UnboundedSender<Message>,
+    abort: Arc<AtomicBool>,
+    wakeup: Arc<Notify>,
+    echo: bool,
+}
+
+impl Bridge {
+    fn check_abort(&self) -> Result<(), String> {
+        match self.abort.load(Ordering::Relaxed) {
+            true => Err("aborted".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.check_abort()?;
+        let (request, reply) = Request::new(self.source, self.name.clone(), method, params);
+        if self.tx.send(Message::Request(request)).is_err() {
+            return Err("session closed".to_string());
+        }
+        self.wakeup.notify_one();
+        match reply.blocking_recv() {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err(format!("{} failed: {}", method, e)),
+            Err(_) => Err("session closed".to_string()),
+        }
+    }
+
+    pub fn register(&self, group: &str, name: &str) -> Result<Value, String> {
+        let info = find_register(group, name)
+            .ok_or_else(|| format!("unknown register {} {}", group, name))?;
+        let registers = self.call("get", json!({ "group": info.group }))?;
+        registers
+            .as_array()
+            .and_then(|registers| registers.iter().find(|reg| reg["name"] == info.name))
+            .cloned()
+            .ok_or_else(|| format!("no telemetry for {} {}", group, name))
+    }
+
+    pub fn read(&self, group: &str, name: &str) -> Result<i64, String> {
+        let reg = self.register(group, name)?;
+        if reg["parity"] == "parity_error" {
+            return Err(format!("parity error reading {} {}", group, name));
+        }
+        Ok(reg["value"].as_i64().unwrap_or_default())
+    }
+
+    pub fn read_retry(&self, group: &str, name: &str, timeout: Duration) -> Result<i64, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.read(group, name) {
+                Err(e) if Instant::now() >= deadline || e == "aborted" => return Err(e),
+                Err(_) => self.sleep(READ_RETRY)?,
+                value => return value,
+            }
+        }
+    }
+
+    pub fn write(&self, group: &str, name: &str, value: i64) -> Result<(), String> {
+        let params = json!({ "group": group, "name": name, "value": value });
+        self.call("write", params).map(|_| ())
+    }
+
+    pub fn status_bits(&self) -> Result<Vec<String>, String> {
+        let reg = self.register("status", "status")?;
+        Ok(reg["bits"]
+            .as_array()
+            .map(|bits| {
+                bits.iter()
+                    .filter_map(|bit| bit.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn status_bit(&self, name: &str) -> Result<bool, String> {
+        if !STATUS_BITS.contains(&name) {
+            return Err(format!("unknown status bit {}", name));
+        }
+        Ok(self.status_bits()?.iter().any(|bit| bit == name))
+    }
+
+    fn state(&self) -> Result<String, String> {
+        let status = self.call("status", Value::Null)?;
+        Ok(status["state"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub fn sleep(&self, ms: i64) -> Result<(), String> {
+        let until = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+        while Instant::now() < until {
+            self.check_abort()?;
+            thread::sleep(SLEEP_STEP.min(until - Instant::now()));
+        }
+        Ok(())
+    }
+
+    pub fn log(&self, text: &str) {
+        if self.echo {
+            println!("{}", text);
+        }
+        let _ = self.tx.send(Message::Log(text.to_string()));
+        self.wakeup.notify_one();
+    }
+}
+
+impl Script {
+    pub fn new(path: &Path, echo: bool, wakeup: Arc<Notify>) -> Script {
+        let file = path.to_path_buf();
+        Script::spawn("script", path, echo, wakeup, move |bridge| {
+            engine(bridge).run_file(file).map_err(|e| e.to_string())
+        })
+    }
+
+    pub fn spawn(
+        source: &'static str,
+        path: &Path,
+        echo: bool,
+        wakeup: Arc<Notify>,
+        job: impl FnOnce(&Bridge) -> Result<(), String> + Send + 'static,
+    ) -> Script {
+        let (tx, rx) = unbounded_channel();
+        let abort = Arc::new(AtomicBool::new(false));
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bridge = Bridge {
+            source,
+            name: name.clone(),
+            tx: tx.clone(),
+            abort: abort.clone(),
+            wakeup: wakeup.clone(),
+            echo,
+        };
+        thread::spawn(move || {
+            let result = job(&bridge).map_err(|e| match bridge.abort.load(Ordering::Relaxed) {
+                true => "aborted".to_string(),
+                false => e,
+            });
+            let _ = tx.send(Message::Finished(result));
+            wakeup.notify_one();
+        });
+        Script {
+            name,
+            rx,
+            abort,
+            started: Instant::now(),
+            seq: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn abort(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
+
+    pub fn line(&mut self, stream: Stream, text: &str) -> Line {
+        let text = format!("[{}] {}", self.name, text);
+        self.seq += 1;
+        Line {
+            stream,
+            timestamp: self.started.elapsed(),
+            seq: self.seq,
+            raw: text.clone().into_bytes(),
+            text,
+            partial: false,
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        while let Ok(message) = self.rx.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+fn rhai<T>(result: Result<T, String>) -> RhaiResult<T> {
+    result.map_err(Into::into)
+}
+
+fn engine(bridge: &Bridge) -> Engine {
+    let mut engine = Engine::new();
+    let abort = bridge.abort.clone();
+    engine.on_progress(move |_| abort.load(Ordering::Relaxed).then_some(Dynamic::UNIT));
+    let b = bridge.clone();
+    engine.on_print(move |text| b.log(text));
+    let b = bridge.clone();
+    engine.on_debug(move |text, _, _| b.log(text));
+
+    let b = bridge.clone();
+    engine.register_fn("log", move |text: &str| b.log(text));
+    let b = bridge.clone();
+    engine.register_fn("sleep", move |ms: INT| rhai(b.sleep(ms)));
+    let b = bridge.clone();
+    engine.register_fn("read", move |group: &str, name: &str| {
+        rhai(b.read(group, name))
+    });
+    let b = bridge.clone();
+    engine.register_fn("parity", move |group: &str, name: &str| {
+        rhai(b.register(group, name))
+            .map(|reg| reg["parity"].as_str().unwrap_or_default().to_string())
+    });
+    let b = bridge.clone();
+    engine.register_fn("status_bit", move |name: &str| rhai(b.status_bit(name)));
+    let b = bridge.clone();
+    engine.register_fn("state", move || rhai(b.state()));
+    let b = bridge.clone();
+    engine.register_fn("write", move |group: &str, name: &str, value: INT| {
+        rhai(b.write(group, name, value))
+    });
+    let b = bridge.clone();
+    engine.register_fn("control", move |command: &str| {
+        rhai(b.call("control", json!({ "command": command }))).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn("protection_toggle", move |name: &str| {
+        rhai(b.call("protection_toggle", json!({ "name": name }))).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn("power_toggle", move || {
+        rhai(b.call("power_toggle", Value::Null)).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn("hard_reset", move || {
+        rhai(b.call("hard_reset", Value::Null)).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn("dac_clear", move || {
+        rhai(b.call("dac_clear", Value::Null)).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn(
+        "tnr_launch",
+        move |period: INT, pulse_width: INT, count: INT| {
+            let params = json!({ "period": period, "pulse_width": pulse_width, "count": count });
+            rhai(b.call("tnr_launch", params)).map(|_| ())
+        },
+    );
+    let b = bridge.clone();
+    engine.register_fn("tnr_stop", move || {
+        rhai(b.call("tnr_stop", Value::Null)).map(|_| ())
+    });
+    let b = bridge.clone();
+    engine.register_fn("preset_load", move |name: &str| {
+        rhai(b.call("preset_load", json!({ "name": name }))).map(|_| ())
+    });
+    engine.register_fn(
+        "assert",
+        |condition: bool, message: &str| -> RhaiResult<()> {
+            match condition {
+                true => Ok(()),
+                false => Err(format!("assertion failed: {}", message).into()),
+            }
+        },
+    );
+    engine
+}
@@ -0,0 +1,382 @@
+use regex::Regex;
+use ringbuf::{HeapRb, Rb};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone)]
+pub struct Line {
+    pub stream: Stream,
+    pub timestamp: Duration,
+    pub seq: u64,
+    pub text: String,
+    pub raw: Vec<u8>,
+    pub partial: bool,
+}
+
+impl Line {
+    pub fn display_text(&self) -> &str {
+        let text = self.text.trim_end_matches(['\n', '\r']);
+        match text.rfind('\r') {
+            Some(index) => &text[index + 1..],
+            None => text,
+        }
+    }
+
+    pub fn hex(&self) -> String {
+        self.raw
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+pub struct Scrollback {
+    ring_buffer: HeapRb<Line>,
+    pushed: u64,
+    scroll: usize,
+    follow: bool,
+    viewport: usize,
+    search: Option<Regex>,
+    current_match: Option<u64>,
+    show_timestamps: bool,
+    hex: bool,
+    filter: Option<Stream>,
+}
+
+impl Scrollback {
+    pub fn new(buf_size: usize) -> Scrollback {
+        Scrollback {
+            // an empty ring buffer panics, keep at least the latest line
+            ring_buffer: HeapRb::new(buf_size.max(1)),
+            pushed: 0,
+            scroll: 0,
+            follow: true,
+            viewport: 1,
+            search: None,
+            current_match: None,
+            show_timestamps: false,
+            hex: false,
+            filter: None,
+        }
+    }
+
+    pub fn push(&mut self, line: Line) {
+        let previous = self
+            .ring_buffer
+            .iter_mut()
+            .rev()
+            .find(|previous| previous.stream == line.stream);
+        if let Some(previous) = previous.filter(|previous| previous.partial) {
+            let Line { seq, timestamp, .. } = *previous;
+            *previous = Line {
+                seq,
+                timestamp,
+                ..line
+            };
+            return;
+        }
+        let shown = self.shows(&line);
+        self.ring_buffer.push_overwrite(line);
+        self.pushed += 1;
+        if !self.follow && shown {
+            self.scroll = (self.scroll + 1).min(self.len().saturating_sub(1));
+        }
+    }
+
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = (u64, &Line)> {
+        let first_seq = self.pushed - self.ring_buffer.len() as u64;
+        let (head, tail) = self.ring_buffer.as_slices();
+        (0..head.len() + tail.len())
+            .map(move |index| {
+                let line = match index.checked_sub(head.len()) {
+                    Some(index) => &tail[index],
+                    None => &head[index],
+                };
+                (first_seq + index as u64, line)
+            })
+            .filter(|(_, line)| self.shows(line))
+    }
+
+    pub fn export(&self, path: &Path) -> io::Result<usize> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for line in self.ring_buffer.iter() {
+            let stream = match line.stream {
+                Stream::Stdout => "stdout",
+                Stream::Stderr => "stderr",
+            };
+            writeln!(
+                file,
+                "{:>10.3} {} {}",
+                line.timestamp.as_secs_f64(),
+                stream,
+                line.display_text()
+            )?;
+        }
+        file.flush()?;
+        Ok(self.ring_buffer.len())
+    }
+
+    pub fn len(&self) -> usize {
+        match self.filter {
+            Some(_) => self.ring_buffer.iter().filter(|line| self.shows(line)).count(),
+            None => self.ring_buffer.len(),
+        }
+    }
+
+    fn shows(&self, line: &Line) -> bool {
+        self.filter.is_none_or(|stream| stream == line.stream)
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow
+    }
+
+    pub fn set_viewport(&mut self, height: usize) {
+        self.viewport = height.max(1);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.follow = false;
+        self.scroll = (self.scroll + lines).min(self.len().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.viewport);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.viewport);
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll_up(self.len());
+    }
+
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+        if self.follow {
+            self.scroll = 0;
+        }
+    }
+
+    pub fn show_timestamps(&self) -> bool {
+        self.show_timestamps
+    }
+
+    pub fn toggle_timestamps(&mut self) {
+        self.show_timestamps = !self.show_timestamps;
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex
+    }
+
+    pub fn toggle_hex(&mut self) {
+        self.hex = !self.hex;
+    }
+
+    pub fn filter(&self) -> Option<Stream> {
+        self.filter
+    }
+
+    pub fn cycle_filter(&mut self) {
+        self.filter = match self.filter {
+            None => Some(Stream::Stdout),
+            Some(Stream::Stdout) => Some(Stream::Stderr),
+            Some(Stream::Stderr) => None,
+        };
+        self.scroll = 0;
+        self.follow = true;
+        self.current_match = None;
+    }
+
+    pub fn search(&self) -> Option<&Regex> {
+        self.search.as_ref()
+    }
+
+    pub fn current_match(&self) -> Option<u64> {
+        self.current_match
+    }
+
+    pub fn match_count(&self) -> usize {
+        match &self.search {
+            Some(search) => self
+                .lines()
+                .filter(|(_, line)| search.is_match(&line.text))
+                .count(),
+            None => 0,
+        }
+    }
+
+    pub fn set_search(&mut self, search: Option<Regex>) {
+        self.search = search;
+        self.current_match = None;
+        let bottom = self.len() - self.scroll.min(self.len());
+        if let Some(index) = self.find_match((0..bottom).rev()) {
+            self.jump_to(index);
+        }
+    }
+
+    pub fn next_match(&mut self) {
+        let start = self.current_index().map_or(0, |index| index + 1);
+        if let Some(index) = self.find_match(start..self.len()) {
+            self.jump_to(index);
+        }
+    }
+
+    pub fn previous_match(&mut self) {
+        let end = self.current_index().unwrap_or(self.len());
+        if let Some(index) = self.find_match((0..end).rev()) {
+            self.jump_to(index);
+        }
+    }
+
+    fn current_index(&self) -> Option<usize> {
+        let current = self.current_match?;
+        self.lines().position(|(seq, _)| seq == current)
+    }
+
+    fn find_match(&self, mut indices: impl Iterator<Item = usize>) -> Option<usize> {
+        let search = self.search.as_ref()?;
+        let lines: Vec<_> = self.lines().collect();
+        indices.find(|&index| search.is_match(&lines[index].1.text))
+    }
+
+    fn jump_to(&mut self, index: usize) {
+        let lines: Vec<_> = self.lines().collect();
+        let (seq, len) = (lines[index].0, lines.len());
+        self.current_match = Some(seq);
+        self.follow = false;
+        let bottom = (index + 1 + self.viewport / 2).min(len);
+        self.scroll = len - bottom;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(stream: Stream, text: &str, partial: bool) -> Line {
+        Line {
+            stream,
+            timestamp: Duration::ZERO,
+            seq: 0,
+            text: text.to_string(),
+            raw: text.as_bytes().to_vec(),
+            partial,
+        }
+    }
+
+    fn scrollback(texts: &[&str]) -> Scrollback {
+        let mut scrollback = Scrollback::new(16);
+        for text in texts {
+            scrollback.push(line(Stream::Stdout, text, false));
+        }
+        scrollback
+    }
+
+    #[test]
+    fn search_jumps_to_the_latest_match() {
+        let mut scrollback = scrollback(&["a1", "b", "a2", "c"]);
+        scrollback.set_search(Some(Regex::new("a").unwrap()));
+        assert_eq!(scrollback.match_count(), 2);
+        assert_eq!(scrollback.current_match(), Some(2));
+        assert!(!scrollback.is_following());
+    }
+
+    #[test]
+    fn search_steps_between_matches() {
+        let mut scrollback = scrollback(&["a1", "b", "a2", "c"]);
+        scrollback.set_search(Some(Regex::new("a").unwrap()));
+        scrollback.previous_match();
+        assert_eq!(scrollback.current_match(), Some(0));
+        scrollback.previous_match();
+        assert_eq!(scrollback.current_match(), Some(0));
+        scrollback.next_match();
+        assert_eq!(scrollback.current_match(), Some(2));
+    }
+
+    #[test]
+    fn search_without_matches_keeps_following() {
+        let mut scrollback = scrollback(&["a1", "b"]);
+        scrollback.set_search(Some(Regex::new("z").unwrap()));
+        assert_eq!(scrollback.match_count(), 0);
+        assert_eq!(scrollback.current_match(), None);
+        assert!(scrollback.is_following());
+    }
+
+    #[test]
+    fn partial_line_is_replaced_by_its_continuation() {
+        let mut scrollback = Scrollback::new(16);
+        let mut first = line(Stream::Stdout, "load", true);
+        first.seq = 5;
+        first.timestamp = Duration::from_secs(1);
+        scrollback.push(first);
+        scrollback.push(line(Stream::Stdout, "loading done\n", false));
+        let lines: Vec<_> = scrollback.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].1.text, "loading done\n");
+        assert_eq!(lines[0].1.seq, 5);
+        assert_eq!(lines[0].1.timestamp, Duration::from_secs(1));
+        assert!(!lines[0].1.partial);
+    }
+
+    #[test]
+    fn partial_line_waits_for_its_own_stream() {
+        let mut scrollback = Scrollback::new(16);
+        scrollback.push(line(Stream::Stdout, "load", true));
+        scrollback.push(line(Stream::Stderr, "warning\n", false));
+        scrollback.push(line(Stream::Stdout, "loaded\n", false));
+        let texts: Vec<&str> = scrollback
+            .lines()
+            .map(|(_, line)| line.display_text())
+            .collect();
+        assert_eq!(texts, ["loaded", "warning"]);
+    }
+
+    #[test]
+    fn carriage_return_shows_the_last_update() {
+        let progress = line(Stream::Stdout, "10%\r20%\r30%\r\n", false);
+        assert_eq!(progress.display_text(), "30%");
+    }
+
+    #[test]
+    fn zero_capacity_keeps_the_latest_line() {
+        let mut scrollback = Scrollback::new(0);
+        scrollback.push(line(Stream::Stdout, "first\n", false));
+        scrollback.push(line(Stream::Stdout, "second\n", false));
+        let texts: Vec<&str> = scrollback
+            .lines()
+            .map(|(_, line)| line.display_text())
+            .collect();
+        assert_eq!(texts, ["second"]);
+    }
+
+    #[test]
+    fn lines_run_backwards_from_the_bottom() {
+        let mut scrollback = scrollback(&["a", "b", "c", "d"]);
+        scrollback.cycle_filter();
+        scrollback.push(line(Stream::Stderr, "e", false));
+        let seqs: Vec<u64> = scrollback.lines().rev().map(|(seq, _)| seq).collect();
+        assert_eq!(seqs, [3, 2, 1, 0]);
+    }
+}
@@ -0,0 +1,233 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::register_json,
+    config::config_dir,
+    sspa::{register_map, Register, SERIAL_NUMBER_ADDRESS},
+};
+
+const TNR_FIELDS: [&str; 3] = ["period", "pulse_width", "count"];
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken: String,
+    pub serial_number: Option<u16>,
+    pub registers: Vec<Value>,
+    pub tnr: Option<[u16; 3]>,
+}
+
+pub struct DiffRow {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl DiffRow {
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+pub struct SnapshotDiff {
+    pub old: String,
+    pub new: String,
+    pub rows: Vec<DiffRow>,
+}
+
+impl SnapshotDiff {
+    pub fn changed(&self) -> usize {
+        self.rows.iter().filter(|row| row.changed()).count()
+    }
+}
+
+impl Snapshot {
+    pub fn new(registers: &BTreeMap<u16, Register>, tnr: Option<[u16; 3]>) -> Snapshot {
+        Snapshot {
+            taken: Local::now().to_rfc3339(),
+            serial_number: registers.get(&SERIAL_NUMBER_ADDRESS).map(Register::value),
+            registers: register_map()
+                .iter()
+                .filter_map(|info| Some(register_json(info, registers.get(&info.address)?)))
+                .collect(),
+            tnr,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: Option<PathBuf>) -> Result<PathBuf, String> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let dir = config_dir()
+                    .map(|dir| dir.join("snapshots"))
+                    .unwrap_or_else(|| PathBuf::from("."));
+                fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+                let serial = match self.serial_number {
+                    Some(serial) => serial.to_string(),
+                    None => "unknown".to_string(),
+                };
+                let taken = Local::now().format("%Y%m%d-%H%M%S");
+                dir.join(format!("{}-{}.json", serial, taken))
+            }
+        };
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    fn fields(&self, tnr: bool) -> BTreeMap<String, String> {
+        let mut fields: BTreeMap<String, String> = self
+            .registers
+            .iter()
+            .map(|reg| (field_name(reg), describe(reg)))
+            .collect();
+        if let (Some(tnr), true) = (self.tnr, tnr) {
+            for (name, value) in TNR_FIELDS.iter().zip(tnr) {
+                fields.insert(format!("tnr.{}", name), value.to_string());
+            }
+        }
+        fields
+    }
+}
+
+fn field_name(reg: &Value) -> String {
+    format!(
+        "{}.{}",
+        reg["group"].as_str().unwrap_or_default(),
+        reg["name"].as_str().unwrap_or_default()
+    )
+}
+
+fn describe(reg: &Value) -> String {
+    let mut text = format!(
+        "0x{:04x} {} {}",
+        reg["raw"].as_u64().unwrap_or_default(),
+        reg["value"].as_u64().unwrap_or_default(),
+        reg["parity"].as_str().unwrap_or_default()
+    );
+    if let Some(bits) = reg["bits"].as_array() {
+        let bits: Vec<&str> = bits.iter().filter_map(Value::as_str).collect();
+        text.push_str(&format!(" [{}]", bits.join(",")));
+    }
+    if let Some(state) = reg["state"].as_str() {
+        text.push_str(&format!(" [{}]", state));
+    }
+    text
+}
+
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<DiffRow> {
+    // headless snapshots do not know the TnR settings, only compare them when both sides do
+    let tnr = old.tnr.is_some() && new.tnr.is_some();
+    let old_fields = old.fields(tnr);
+    let new_fields = new.fields(tnr);
+    // keep the register map order, then whatever only one side knows about
+    let mut names: Vec<String> = register_map()
+        .iter()
+        .map(|info| format!("{}.{}", info.group, info.name))
+        .collect();
+    names.extend(TNR_FIELDS.iter().map(|name| format!("tnr.{}", name)));
+    for name in old_fields.keys().chain(new_fields.keys()) {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+        .into_iter()
+        .filter(|name| old_fields.contains_key(name) || new_fields.contains_key(name))
+        .map(|name| DiffRow {
+            old: old_fields.get(&name).cloned().unwrap_or("-".to_string()),
+            new: new_fields.get(&name).cloned().unwrap_or("-".to_string()),
+            field: name,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot(registers: &[(u16, u16)], tnr: Option<[u16; 3]>) -> Snapshot {
+        let registers = registers
+            .iter()
+            .map(|(address, value)| (*address, Register::new(Register::encode(*value))))
+            .collect();
+        Snapshot::new(&registers, tnr)
+    }
+
+    fn row<'a>(rows: &'a [DiffRow], field: &str) -> &'a DiffRow {
+        rows.iter().find(|row| row.field == field).unwrap()
+    }
+
+    #[test]
+    fn marks_changed_registers() {
+        let old = snapshot(&[(0x30, 5), (0x31, 7)], None);
+        let new = snapshot(&[(0x30, 7), (0x31, 7)], None);
+        let rows = diff(&old, &new);
+        assert_eq!(rows.len(), 2);
+        let changed = row(&rows, "dac.output_power");
+        assert!(changed.changed());
+        assert_eq!(changed.old, "0x0005 5 ok");
+        assert_eq!(changed.new, "0x8007 7 ok");
+        assert!(!row(&rows, "dac.reflected_power").changed());
+    }
+
+    #[test]
+    fn register_missing_on_one_side_shows_a_dash() {
+        let rows = diff(&snapshot(&[(0x30, 5)], None), &snapshot(&[(0x31, 5)], None));
+        assert_eq!(row(&rows, "dac.output_power").new, "-");
+        assert_eq!(row(&rows, "dac.reflected_power").old, "-");
+    }
+
+    #[test]
+    fn follows_the_register_map_order() {
+        let registers: Vec<(u16, u16)> = register_map()
+            .iter()
+            .rev()
+            .map(|info| (info.address, 0))
+            .collect();
+        let rows = diff(&snapshot(&registers, None), &snapshot(&registers, None));
+        let fields: Vec<String> = register_map()
+            .iter()
+            .map(|info| format!("{}.{}", info.group, info.name))
+            .collect();
+        assert_eq!(
+            rows.iter().map(|row| &row.field).collect::<Vec<_>>(),
+            fields.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn compares_tnr_only_when_both_sides_know_it() {
+        let live = snapshot(&[(0x30, 5)], Some([100, 10, 3]));
+        let headless = snapshot(&[(0x30, 5)], None);
+        assert!(diff(&live, &headless)
+            .iter()
+            .all(|row| !row.field.starts_with("tnr.")));
+        let rows = diff(&live, &snapshot(&[(0x30, 5)], Some([100, 20, 3])));
+        assert!(row(&rows, "tnr.pulse_width").changed());
+        assert!(!row(&rows, "tnr.period").changed());
+    }
+
+    #[test]
+    fn keeps_fields_unknown_to_this_version_at_the_end() {
+        let mut old = snapshot(&[(0x30, 5)], None);
+        old.registers
+            .push(json!({ "group": "dac", "name": "spare", "raw": 1, "value": 1, "parity": "ok" }));
+        let rows = diff(&old, &snapshot(&[(0x30, 5)], None));
+        let last = rows.last().unwrap();
+        assert_eq!(last.field, "dac.spare");
+        assert_eq!(last.new, "-");
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Clone, Copy)]
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum RegisterState {
     ParityError,
     Warning,
@@ -9,29 +11,54 @@ pub enum RegisterState {
 pub struct Register {
     state: RegisterState,
     value: u16,
+    raw: u16,
 }
 
 impl Register {
 
     pub fn new(value: u16) -> Register {
-        let parity = value.count_ones() % 2 == 0;
+        let parity = value.count_ones().is_multiple_of(2);
         let state = match (parity, value) {
             (false, _) => RegisterState::ParityError,
             (true, u16::MAX) => RegisterState::Warning,
             (true, _) => RegisterState::Ok,
         };
+        let raw = value;
         let value = value&0x7FFF;
-        Register { state, value }
+        Register { state, value, raw }
     }
 
     pub fn value(&self) -> u16 {
         self.value
     }
 
+    pub fn raw(&self) -> u16 {
+        self.raw
+    }
+
     pub fn state(&self) -> RegisterState {
         self.state
     }
 
+    pub fn encode(value: u16) -> u16 {
+        let value = value & 0x7FFF;
+        if value.count_ones().is_multiple_of(2) {
+            value
+        } else {
+            value | 0x8000
+        }
+    }
+
+}
+
+impl fmt::Display for RegisterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterState::Ok => write!(f, "ok"),
+            RegisterState::Warning => write!(f, "warning"),
+            RegisterState::ParityError => write!(f, "parity_error"),
+        }
+    }
 }
 
 pub fn bits(reg: &Register) -> [bool; 15] {
@@ -43,8 +70,146 @@ pub fn bits(reg: &Register) -> [bool; 15] {
         .unwrap()
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
+pub const STATUS_ADDRESS: u16 = 0x00;
+pub const STATE_ADDRESS: u16 = 0x01;
+pub const VERSION_ADDRESS: u16 = 0x02;
+pub const CONTROL_ADDRESS: u16 = 0x03;
+pub const ADC_ADDRESS: u16 = 0x10;
+pub const THRESHOLDS_ADDRESS: u16 = 0x20;
+pub const DAC_ADDRESS: u16 = 0x30;
+pub const OFFSETS_ADDRESS: u16 = 0x40;
+pub const SERIAL_NUMBER_ADDRESS: u16 = THRESHOLDS_ADDRESS + 9;
+
+pub const THRESHOLD_NAMES: [&str; 10] = [
+    "over_temperature",
+    "temperature_hysteresis",
+    "over_current",
+    "duty_cycle",
+    "pulse_length",
+    "over_drive",
+    "under_drive",
+    "output_power",
+    "reflected_power",
+    "serial_number",
+];
+
+pub const CHANNEL_NAMES: [&str; 8] = [
+    "output_power",
+    "reflected_power",
+    "drive_level",
+    "temperature",
+    "gan_1_current",
+    "gan_2_current",
+    "gan_3_current",
+    "gan_4_current",
+];
+
+pub const STATUS_BITS: [&str; 15] = [
+    "SSPA_Active",
+    "HW_Reflected_Power",
+    "HW_Over_temperature",
+    "HW_Over_drive",
+    "HW_Gan1",
+    "HW_Gan2",
+    "HW_Gan3",
+    "HW_Gan4",
+    "SW_Reflected_Power",
+    "SW_Direct_Power",
+    "SW_Under_drive",
+    "SW_Over_drive",
+    "SW_Duty_Cycle",
+    "SW_Over_Temperature",
+    "SW_Over_Current",
+];
+
+pub const CONTROL_BITS: [&str; 15] = [
+    "Store_NVM",
+    "Load_NVM",
+    "Alarms_Reset",
+    "SSPA_Reset",
+    "SSPA_Disable",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "SW_Reflected_Power_Disable",
+    "SW_Over_drive_Disable",
+    "SW_Duty_Cycle_Disable",
+    "SW_Over_Temperature_Disable",
+    "SW_Over_Current_Disable",
+];
+
+pub struct RegisterInfo {
+    pub group: &'static str,
+    pub name: &'static str,
+    pub address: u16,
+}
+
+pub fn register_map() -> Vec<RegisterInfo> {
+    let mut map = vec![
+        RegisterInfo {
+            group: "status",
+            name: "status",
+            address: STATUS_ADDRESS,
+        },
+        RegisterInfo {
+            group: "state",
+            name: "state",
+            address: STATE_ADDRESS,
+        },
+        RegisterInfo {
+            group: "version",
+            name: "version",
+            address: VERSION_ADDRESS,
+        },
+        RegisterInfo {
+            group: "control",
+            name: "control",
+            address: CONTROL_ADDRESS,
+        },
+    ];
+    let groups: [(&str, &[&'static str], u16); 4] = [
+        ("adc", &CHANNEL_NAMES, ADC_ADDRESS),
+        ("threshold", &THRESHOLD_NAMES, THRESHOLDS_ADDRESS),
+        ("dac", &CHANNEL_NAMES, DAC_ADDRESS),
+        ("offset", &CHANNEL_NAMES, OFFSETS_ADDRESS),
+    ];
+    for (group, names, base) in groups {
+        for (i, name) in names.iter().enumerate() {
+            map.push(RegisterInfo {
+                group,
+                name,
+                address: base + i as u16,
+            });
+        }
+    }
+    map
+}
+
+pub fn find_register(group: &str, name: &str) -> Option<RegisterInfo> {
+    register_map()
+        .into_iter()
+        .filter(|info| info.group == group)
+        .enumerate()
+        .find(|(i, info)| info.name == name || i.to_string() == name)
+        .map(|(_, info)| info)
+}
+
+// the serial number identifies the unit in reports and the audit log, never overwrite it
+pub fn is_writable(info: &RegisterInfo) -> bool {
+    ["threshold", "dac", "offset"].contains(&info.group) && info.address != SERIAL_NUMBER_ADDRESS
+}
+
+pub const PROTECTION_NAMES: [&str; 5] = [
+    "reflected_power",
+    "over_drive",
+    "duty_cycle",
+    "over_temperature",
+    "over_current",
+];
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum SSPAState {
     Invalid,
     Boot,
@@ -57,3 +222,35 @@ pub enum SSPAState {
     Protection,
 }
 
+impl fmt::Display for SSPAState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SSPAState::Invalid => "Invalid",
+            SSPAState::Boot => "Boot",
+            SSPAState::StandBy => "StandBy",
+            SSPAState::Failure => "Failure",
+            SSPAState::Disabled => "Disabled",
+            SSPAState::Nominal => "Nominal",
+            SSPAState::Warning => "Warning",
+            SSPAState::ProtectionHW => "ProtectionHW",
+            SSPAState::Protection => "Protection",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SSPAState {
+    pub fn from_value(value: u16) -> SSPAState {
+        match value {
+            1 => SSPAState::Boot,
+            2 => SSPAState::StandBy,
+            3 => SSPAState::Failure,
+            4 => SSPAState::Disabled,
+            5 => SSPAState::Nominal,
+            6 => SSPAState::Warning,
+            7 => SSPAState::ProtectionHW,
+            8 => SSPAState::Protection,
+            _ => SSPAState::Invalid,
+        }
+    }
+}
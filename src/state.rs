@@ -1,10 +1,64 @@
-use tui::widgets::ListState;
+use chrono::Local;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{process::Command as Process, sync::Notify};
+use tui::{layout::Rect, widgets::ListState};
 
 use crate::{
-    sspa::{Register, SSPAState},
-    ui::WidgetId,
+    action::{item_action, Action, CompileStep},
+    api::{self, Api, Call, Request},
+    audit::{Audit, AuditEntry},
+    backup::Backup,
+    cli::register_json,
+    config::{config_dir, user_name, CompileConfig, Config},
+    connection::{Connection, ConnectionState},
+    device::{self, DeviceCommand},
+    diagnostics::{Diagnostics, DiagnosticsSender},
+    keymap::Keymap,
+    layout::{self, LayoutNode},
+    launcher::Launcher,
+    limits::{LimitEvent, Limits},
+    metrics::{Exposition, Metrics},
+    palette::{self, Command, CommandKind},
+    plan::{Plan, Report},
+    script::{Message, Script},
+    scrollback::{Scrollback, Stream},
+    snapshot::{self, Snapshot, SnapshotDiff},
+    sspa::{
+        bits, register_map, Register, RegisterState, SSPAState, ADC_ADDRESS, CHANNEL_NAMES,
+        CONTROL_ADDRESS, CONTROL_BITS, DAC_ADDRESS, OFFSETS_ADDRESS, SERIAL_NUMBER_ADDRESS,
+        STATE_ADDRESS, STATUS_ADDRESS, STATUS_BITS, THRESHOLDS_ADDRESS, THRESHOLD_NAMES,
+        VERSION_ADDRESS,
+    },
+    telemetry,
+    theme::Theme,
+    ui::{chunk_index, WidgetId, WIDGET_COUNT},
 };
 
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+const READBACK_TIMEOUT: Duration = Duration::from_secs(5);
+pub const WIDGETS: [WidgetId; WIDGET_COUNT] = [
+    WidgetId::Registers,
+    WidgetId::HardReset,
+    WidgetId::Ext,
+    WidgetId::ExtPresets,
+    WidgetId::Compile,
+    WidgetId::Dac,
+    WidgetId::Offsets,
+    WidgetId::Control,
+    WidgetId::Ssh,
+    WidgetId::Terminal,
+    WidgetId::Log,
+    WidgetId::Audit,
+];
+
 pub struct StateKeeper {
     status_register: Register,
     adc: [Register; 8],
@@ -17,12 +71,59 @@ pub struct StateKeeper {
     dac: [u16; 8],
     offsets: [u16; 8],
     control_register: Register,
+    connection_state: ConnectionState,
+    registers: BTreeMap<u16, Register>,
+    parity_errors: BTreeMap<u16, u64>,
+    metrics: Option<Metrics>,
+    api: Option<Api>,
+    script: Option<Script>,
+    script_result: Option<Result<(), String>>,
+    limits: Limits,
+    bell: bool,
+    audit: Audit,
+    read_only: bool,
+    origin: Option<String>,
+    flash: Option<AuditEntry>,
+    pending_writes: Vec<(u16, u16, Instant, AuditEntry)>,
+    terminal: Launcher,
+    ssh: Connection,
+    diagnostics: Diagnostics,
+    wakeup: Arc<Notify>,
+    keymap: Keymap,
+    help: bool,
+    diff: Option<SnapshotDiff>,
+    diff_scroll: usize,
+    restore: Option<(PathBuf, Backup)>,
+    // a finished test run hands its report over for the operator to sign off
+    finished_report: Arc<Mutex<Option<Report>>>,
+    sign_off: Option<Report>,
+    search_input: Option<String>,
+    edit: Option<Edit>,
+    palette: Option<Palette>,
+    presets: Vec<(String, [u16; 3])>,
+    layouts: Vec<(String, Option<LayoutNode>)>,
+    layout: usize,
+    theme: Theme,
+    colors: BTreeMap<String, String>,
+    compile: CompileConfig,
+    compile_step: Option<CompileStep>,
     selected_widget: WidgetId,
-    widget_transition: [[WidgetId; 4]; 8],
-    list_state: [ListState; 8],
-    list_element_count: [usize; 8],
+    list_state: [ListState; WIDGET_COUNT],
+    list_element_count: [usize; WIDGET_COUNT],
+    areas: [Option<WidgetArea>; WIDGET_COUNT],
+    tabs: Vec<(Rect, WidgetId)>,
+    zoom: bool,
+    tabbed: bool,
+    last_click: Option<(Instant, WidgetId, usize)>,
+}
+
+struct WidgetArea {
+    rect: Rect,
+    item_heights: Vec<usize>,
+    offset: usize,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum StateTransition {
     Left,
     Down,
@@ -32,11 +133,92 @@ pub enum StateTransition {
 
 pub type ExtSignals = (bool, [u16; 3], [u16; 3]);
 
+struct Palette {
+    input: String,
+    selected: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EditTarget {
+    Threshold(usize),
+    Dac(usize),
+    Offset(usize),
+    Tnr(usize),
+}
+
+struct Edit {
+    target: EditTarget,
+    input: String,
+}
+
 impl StateKeeper {
-    pub fn new() -> StateKeeper {
+    pub fn new(config: &Config) -> StateKeeper {
+        let mut state = StateKeeper::headless(config);
+        let diagnostics = state.diagnostics.sender();
+        state.metrics = config
+            .metrics_port
+            .map(|port| Metrics::new(port, diagnostics.clone()));
+        state.api = config
+            .api_socket
+            .as_deref()
+            .map(|path| Api::new(path, diagnostics, state.wakeup.clone()));
+        // scrapers get a full exposition before the first telemetry line
+        state.publish_metrics();
+        state
+    }
+
+    // the session without the metrics port and the API socket, for one-shot CLI runs
+    pub fn headless(config: &Config) -> StateKeeper {
+        let wakeup = Arc::new(Notify::new());
+        let diagnostics = Diagnostics::new(
+            config.scrollback,
+            config.diagnostics_file.as_deref(),
+            wakeup.clone(),
+        );
+        let (keymap, problems) = Keymap::new(&config.keys);
+        for problem in problems {
+            diagnostics.sender().warning("keymap", problem);
+        }
+        let (layouts, problems) = layout::load(config);
+        for problem in problems {
+            diagnostics.sender().warning("layout", problem);
+        }
+        let layout_name = layout::saved_layout().or(config.layout.clone());
+        let layout = match layout_name {
+            Some(name) => layouts
+                .iter()
+                .position(|(existing, _)| *existing == name)
+                .unwrap_or_else(|| {
+                    diagnostics
+                        .sender()
+                        .warning("layout", format!("unknown layout {}", name));
+                    0
+                }),
+            None => 0,
+        };
+        let (theme, problems) = Theme::new(config.theme).with_colors(&config.colors);
+        for problem in problems {
+            diagnostics.sender().warning("theme", problem);
+        }
+        let (limits, problems) = Limits::new(&config.limits);
+        for problem in problems {
+            diagnostics.sender().warning("limits", problem);
+        }
+        let terminal = Launcher::new(
+            "terminal",
+            config.scrollback,
+            diagnostics.sender(),
+            wakeup.clone(),
+        );
+        let ssh = Connection::new(
+            &config.ssh_command,
+            config.scrollback,
+            diagnostics.sender(),
+            wakeup.clone(),
+        );
         let mut list_state_1 = ListState::default();
         list_state_1.select(Some(0));
-        StateKeeper {
+        let mut state = StateKeeper {
             status_register: Register::new(0),
             adc: [Register::new(0); 8],
             thresholds: [Register::new(0); 10],
@@ -48,57 +230,46 @@ impl StateKeeper {
             dac: [0; 8],
             offsets: [0; 8],
             control_register: Register::new(0),
+            connection_state: ConnectionState::Reconnecting,
+            registers: BTreeMap::new(),
+            parity_errors: BTreeMap::new(),
+            metrics: None,
+            api: None,
+            script: None,
+            script_result: None,
+            limits,
+            bell: false,
+            audit: Audit::new(config, diagnostics.sender()),
+            read_only: config.read_only,
+            origin: None,
+            flash: None,
+            pending_writes: Vec::new(),
+            terminal,
+            ssh,
+            diagnostics,
+            wakeup,
+            keymap,
+            help: false,
+            diff: None,
+            diff_scroll: 0,
+            restore: None,
+            finished_report: Arc::default(),
+            sign_off: None,
+            search_input: None,
+            edit: None,
+            palette: None,
+            presets: config
+                .presets
+                .iter()
+                .map(|(name, preset)| (name.clone(), *preset))
+                .collect(),
+            layouts,
+            layout,
+            theme,
+            colors: config.colors.clone(),
+            compile: config.compile.clone(),
+            compile_step: None,
             selected_widget: WidgetId::Ext,
-            widget_transition: [
-                [
-                    WidgetId::Registers,
-                    WidgetId::HardReset,
-                    WidgetId::Registers,
-                    WidgetId::ExtPresets,
-                ],
-                [
-                    WidgetId::HardReset,
-                    WidgetId::HardReset,
-                    WidgetId::Registers,
-                    WidgetId::Compile,
-                ],
-                [
-                    WidgetId::Registers,
-                    WidgetId::ExtPresets,
-                    WidgetId::Ext,
-                    WidgetId::Dac,
-                ],
-                [
-                    WidgetId::Registers,
-                    WidgetId::Compile,
-                    WidgetId::Ext,
-                    WidgetId::Offsets,
-                ],
-                [
-                    WidgetId::HardReset,
-                    WidgetId::Compile,
-                    WidgetId::ExtPresets,
-                    WidgetId::Offsets,
-                ],
-                [
-                    WidgetId::Ext,
-                    WidgetId::Offsets,
-                    WidgetId::Dac,
-                    WidgetId::Control,
-                ],
-                [
-                    WidgetId::ExtPresets,
-                    WidgetId::Offsets,
-                    WidgetId::Dac,
-                    WidgetId::Control,
-                ],
-                [
-                    WidgetId::Dac,
-                    WidgetId::Control,
-                    WidgetId::Control,
-                    WidgetId::Control,
-                ],
-            ],
             list_state: [
                 ListState::default(),
                 ListState::default(),
@@ -108,18 +279,37 @@ impl StateKeeper {
                 ListState::default(),
                 ListState::default(),
                 ListState::default(),
+                ListState::default(),
+                ListState::default(),
+                ListState::default(),
+                ListState::default(),
             ],
             list_element_count: [
                 10,
                 1,
                 8,
-                1,
+                config.presets.len(),
                 5,
                 9,
                 8,
                 10,
+                0,
+                0,
+                0,
+                0,
             ],
-        }
+            areas: Default::default(),
+            tabs: Vec::new(),
+            zoom: false,
+            tabbed: false,
+            last_click: None,
+        };
+        state.focus_layout();
+        state
+    }
+
+    pub fn launch_terminal(&mut self, command: &str) {
+        self.terminal.launch(command);
     }
 
     pub fn status_register(&self) -> Register {
@@ -158,12 +348,1314 @@ impl StateKeeper {
         self.control_register
     }
 
+    pub fn presets(&self) -> &[(String, [u16; 3])] {
+        &self.presets
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection_state
+    }
+
+    pub fn wakeup(&self) -> Arc<Notify> {
+        self.wakeup.clone()
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn help(&self) -> bool {
+        self.help
+    }
+
+    pub fn snapshot_diff(&self) -> Option<(&SnapshotDiff, usize)> {
+        self.diff.as_ref().map(|diff| (diff, self.diff_scroll))
+    }
+
+    pub fn scroll_diff(&mut self, down: bool) {
+        let rows = self.diff.as_ref().map_or(0, |diff| diff.rows.len());
+        self.diff_scroll = match down {
+            true => (self.diff_scroll + 1).min(rows.saturating_sub(1)),
+            false => self.diff_scroll.saturating_sub(1),
+        };
+    }
+
+    pub fn close_diff(&mut self) {
+        self.diff = None;
+        self.restore = None;
+    }
+
+    pub fn restore_pending(&self) -> bool {
+        self.restore.is_some()
+    }
+
+    fn backup_config(&self, path: Option<PathBuf>) {
+        let diagnostics = self.diagnostics.sender();
+        match Backup::new(&self.registers).and_then(|backup| backup.save(path)) {
+            Ok(path) => diagnostics.info("backup", format!("saved {}", path.display())),
+            Err(e) => diagnostics.error("backup", format!("failed to save backup: {}", e)),
+        }
+    }
+
+    fn preview_restore(&mut self, path: PathBuf) {
+        let backup = match Backup::load(&path) {
+            Ok(backup) => backup,
+            Err(e) => {
+                self.diagnostics
+                    .sender()
+                    .error("backup", format!("failed to read {}: {}", path.display(), e));
+                return;
+            }
+        };
+        self.diff = Some(SnapshotDiff {
+            old: "unit".to_string(),
+            new: path.display().to_string(),
+            rows: backup.preview(&self.registers),
+        });
+        self.diff_scroll = 0;
+        self.restore = Some((path, backup));
+    }
+
+    pub fn confirm_restore(&mut self, store: bool) {
+        let restore = self.restore.take();
+        self.diff = None;
+        if let Some((path, backup)) = restore {
+            if !self.check_idle() {
+                return;
+            }
+            let wakeup = self.wakeup.clone();
+            let script = Script::spawn("restore", &path, false, wakeup, move |bridge| {
+                backup.restore(bridge, true, store)
+            });
+            self.start_script(script, &path);
+        }
+    }
+
+    pub fn run_restore(&mut self, path: &Path, write: bool, store: bool) {
+        if !self.check_idle() {
+            return;
+        }
+        let backup = match Backup::load(path) {
+            Ok(backup) => backup,
+            Err(e) => {
+                self.diagnostics
+                    .sender()
+                    .error("backup", format!("invalid backup {}: {}", path.display(), e));
+                self.script_result = Some(Err(e));
+                return;
+            }
+        };
+        let wakeup = self.wakeup.clone();
+        let script = Script::spawn("restore", path, true, wakeup, move |bridge| {
+            backup.restore(bridge, write, store)
+        });
+        self.start_script(script, path);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::new(&self.registers, Some(self.current_tnr))
+    }
+
+    fn save_snapshot(&self, path: Option<PathBuf>) {
+        let diagnostics = self.diagnostics.sender();
+        match self.snapshot().save(path) {
+            Ok(path) => diagnostics.info("snapshot", format!("saved {}", path.display())),
+            Err(e) => diagnostics.error("snapshot", format!("failed to save snapshot: {}", e)),
+        }
+    }
+
+    fn diff_snapshots(&mut self, files: &str) {
+        let diagnostics = self.diagnostics.sender();
+        let mut files = files
+            .split_whitespace()
+            .map(|file| find_file(PathBuf::from(file), "snapshots"));
+        let (old_path, new_path) = match (files.next(), files.next(), files.next()) {
+            (Some(old), new, None) => (old, new),
+            _ => {
+                diagnostics.warning("snapshot", "diff needs one or two snapshot files");
+                return;
+            }
+        };
+        let load = |path: &Path| {
+            Snapshot::load(path).map_err(|e| {
+                diagnostics.error("snapshot", format!("failed to read {}: {}", path.display(), e))
+            })
+        };
+        let old = match load(&old_path) {
+            Ok(old) => old,
+            Err(()) => return,
+        };
+        let (new, new_name) = match &new_path {
+            Some(path) => match load(path) {
+                Ok(new) => (new, path.display().to_string()),
+                Err(()) => return,
+            },
+            None => (self.snapshot(), "live".to_string()),
+        };
+        self.diff = Some(SnapshotDiff {
+            old: old_path.display().to_string(),
+            new: new_name,
+            rows: snapshot::diff(&old, &new),
+        });
+        self.diff_scroll = 0;
+    }
+
+    pub fn selected_widget(&self) -> WidgetId {
+        self.selected_widget
+    }
+
+    pub fn item_count(&self, wid: WidgetId) -> usize {
+        self.list_element_count[wid as usize]
+    }
+
+    pub fn poll(&mut self) -> bool {
+        let mut changed = self.diagnostics.poll();
+        changed |= !self.terminal.poll().is_empty();
+        changed |= self.check_flash();
+        self.expire_writes(Some(Instant::now()));
+        let lines = self.ssh.poll();
+        changed |= !lines.is_empty();
+        for line in lines.iter().filter(|line| !line.partial) {
+            if let Some((address, raw)) = telemetry::parse_line(&line.text) {
+                self.apply_register(address, raw);
+            }
+        }
+        // a transition alone is a change, so the connection gauge is republished below
+        if self.connection_state != self.ssh.state() {
+            changed = true;
+            self.connection_state = self.ssh.state();
+            self.publish_event("connection", json!(self.connection_state.to_string()));
+        }
+        let requests = self.api.as_mut().map(Api::poll).unwrap_or_default();
+        for request in requests {
+            changed = true;
+            self.handle_request(request);
+        }
+        changed |= self.check_limits();
+        changed |= self.poll_script();
+        if changed {
+            self.publish_metrics();
+        }
+        changed
+    }
+
+    pub fn run_script(&mut self, path: &Path, echo: bool) {
+        if !self.check_idle() {
+            return;
+        }
+        let script = Script::new(path, echo, self.wakeup.clone());
+        self.start_script(script, path);
+    }
+
+    pub fn run_plan(&mut self, path: &Path, echo: bool) {
+        if !self.check_idle() {
+            return;
+        }
+        let plan = match Plan::load(path) {
+            Ok(plan) => plan,
+            Err(e) => {
+                self.diagnostics
+                    .sender()
+                    .error("test", format!("invalid plan {}: {}", path.display(), e));
+                self.script_result = Some(Err(e));
+                return;
+            }
+        };
+        let wakeup = self.wakeup.clone();
+        let finished = self.finished_report.clone();
+        let script = Script::spawn("test", path, echo, wakeup, move |bridge| {
+            let report = plan.run(bridge)?;
+            let outcome = report.outcome();
+            *finished.lock().expect("ERROR: report lock poisoned") = Some(report);
+            outcome
+        });
+        self.start_script(script, path);
+    }
+
+    pub fn sign_off(&self) -> Option<&Report> {
+        self.sign_off.as_ref()
+    }
+
+    pub fn sign_report(&mut self) -> Result<PathBuf, String> {
+        let diagnostics = self.diagnostics.sender();
+        let mut report = self.sign_off.take().ok_or("no report to sign off")?;
+        let operator = user_name();
+        let result = report.sign(operator.clone());
+        match &result {
+            Ok(path) => diagnostics.info(
+                "test",
+                format!("{} signed off {} ({})", operator, report.verdict(), path.display()),
+            ),
+            Err(e) => diagnostics.error("test", format!("failed to sign report: {}", e)),
+        }
+        result
+    }
+
+    pub fn leave_unsigned(&mut self) {
+        if let Some(report) = self.sign_off.take() {
+            self.diagnostics.sender().warning(
+                "test",
+                format!("report for unit {} left unsigned", report.serial_number()),
+            );
+        }
+    }
+
+    fn check_idle(&self) -> bool {
+        if let Some(script) = &self.script {
+            self.diagnostics
+                .sender()
+                .warning("script", format!("{} is still running", script.name()));
+            return false;
+        }
+        true
+    }
+
+    fn start_script(&mut self, mut script: Script, path: &Path) {
+        let line = script.line(Stream::Stdout, "started, press Esc to abort");
+        self.terminal.scrollback_mut().push(line);
+        self.diagnostics
+            .sender()
+            .info("script", format!("running {}", path.display()));
+        self.script = Some(script);
+        self.script_result = None;
+    }
+
+    pub fn script_running(&self) -> Option<&str> {
+        self.script.as_ref().map(Script::name)
+    }
+
+    // the bell is rung by the interactive loop, headless runs only log the trip
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    pub fn script_result(&self) -> Option<&Result<(), String>> {
+        self.script_result.as_ref()
+    }
+
+    pub fn abort_script(&mut self) {
+        if let Some(script) = &self.script {
+            script.abort();
+        }
+    }
+
+    fn poll_script(&mut self) -> bool {
+        let messages = match &mut self.script {
+            Some(script) => script.poll(),
+            None => return false,
+        };
+        let changed = !messages.is_empty();
+        for message in messages {
+            let script = match &mut self.script {
+                Some(script) => script,
+                None => break,
+            };
+            match message {
+                Message::Request(request) => self.handle_request(request),
+                Message::Log(text) => {
+                    let line = script.line(Stream::Stdout, &text);
+                    self.terminal.scrollback_mut().push(line);
+                }
+                Message::Finished(result) => {
+                    let diagnostics = self.diagnostics.sender();
+                    let line = match &result {
+                        Ok(()) => {
+                            diagnostics.info("script", format!("{} passed", script.name()));
+                            script.line(Stream::Stdout, "passed")
+                        }
+                        Err(e) => {
+                            diagnostics.error("script", format!("{} failed: {}", script.name(), e));
+                            script.line(Stream::Stderr, &format!("failed: {}", e))
+                        }
+                    };
+                    self.terminal.scrollback_mut().push(line);
+                    let aborted = result.as_ref().is_err_and(|e| e == "aborted");
+                    self.script_result = Some(result);
+                    self.script = None;
+                    self.sign_off = self
+                        .finished_report
+                        .lock()
+                        .expect("ERROR: report lock poisoned")
+                        .take();
+                    if aborted {
+                        self.leave_safe();
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    // an aborted script may leave the unit mid sequence, stop TnR and report what it was left in
+    fn leave_safe(&mut self) {
+        let diagnostics = self.diagnostics.sender();
+        if let Err(e) = self.run_action(Action::TnrStop) {
+            diagnostics.error("abort", format!("tnr stop failed: {}", e));
+        }
+        let dac = CHANNEL_NAMES
+            .iter()
+            .zip(self.dac.iter())
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        diagnostics.warning(
+            "abort",
+            format!(
+                "unit left in {}, power enable {}, tnr {:?}, dac {}",
+                self.sspa_state,
+                if self.powen { "on" } else { "off" },
+                self.current_tnr,
+                dac
+            ),
+        );
+    }
+
+    fn check_limits(&mut self) -> bool {
+        let received = (ADC_ADDRESS..ADC_ADDRESS + 8).all(|a| self.registers.contains_key(&a));
+        if !received {
+            return false;
+        }
+        let events = self.limits.evaluate(&self.adc);
+        let diagnostics = self.diagnostics.sender();
+        for event in &events {
+            let (i, value, active) = match *event {
+                LimitEvent::Tripped(i, value) => (i, value, true),
+                LimitEvent::Cleared(i, value) => (i, value, false),
+            };
+            let rule = self.limits.rule(i);
+            if active {
+                diagnostics.warning("limits", format!("{} tripped at {:.1}", rule.name, value));
+                self.bell |= rule.bell;
+                if let Some(command) = &rule.command {
+                    run_limit_command(command, &rule.name, value, diagnostics.clone());
+                }
+            } else {
+                diagnostics.info("limits", format!("{} cleared at {:.1}", rule.name, value));
+            }
+            let data = json!({ "name": rule.name, "active": active, "value": value });
+            self.publish_event("limit", data);
+        }
+        !events.is_empty()
+    }
+
+    pub fn limit_violations(&self) -> [bool; 8] {
+        self.limits.violated_channels()
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn active_limits(&self) -> usize {
+        self.limits.active()
+    }
+
+    fn publish_event(&self, event: &str, data: Value) {
+        if let Some(api) = &self.api {
+            api.publish(event, data);
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) {
+        let call = match api::parse_call(&request.method, &request.params) {
+            Ok(call) => call,
+            Err(e) => {
+                request.respond(Err(e));
+                return;
+            }
+        };
+        let description = match &call {
+            Call::Get(_) | Call::Status => None,
+            Call::Action(action) => Some(action.to_string()),
+            Call::TnrLaunch(Some(tnr)) => Some(format!("launch TnR {:?}", tnr)),
+            Call::TnrLaunch(None) => Some(Action::TnrLaunch.to_string()),
+            Call::LoadPreset(name) => Some(format!("load preset {}", name)),
+        };
+        if let Some(description) = &description {
+            let diagnostics = self.diagnostics.sender();
+            let device = !matches!(call, Call::LoadPreset(_));
+            if device && self.connection_state != ConnectionState::Connected {
+                diagnostics.warning(
+                    request.source,
+                    format!(
+                        "{}: not connected, refused {}",
+                        request.client, description
+                    ),
+                );
+                request.respond(Err("not connected".to_string()));
+                return;
+            }
+            diagnostics.info(request.source, format!("{}: {}", request.client, description));
+            self.publish_event(
+                "action",
+                json!({ "client": request.client, "action": description }),
+            );
+        }
+        self.origin = Some(format!("{} {}", request.source, request.client));
+        let result = match call {
+            Call::Get(group) => Ok(Value::Array(
+                register_map()
+                    .iter()
+                    .filter(|info| group.as_deref().is_none_or(|group| info.group == group))
+                    .filter_map(|info| {
+                        Some(register_json(info, self.registers.get(&info.address)?))
+                    })
+                    .collect(),
+            )),
+            Call::Status => Ok(json!({
+                "state": self.sspa_state.to_string(),
+                "connection": self.connection_state.to_string(),
+                "power_enable": self.powen,
+                "tnr": self.current_tnr,
+                "presets": self.presets.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            })),
+            Call::Action(action) => self.run_action(action).map(|_| Value::Bool(true)),
+            Call::TnrLaunch(tnr) => {
+                // the edited values only stick once the unit took them
+                let cached = self.cache_tnr;
+                if let Some(tnr) = tnr {
+                    self.cache_tnr = tnr;
+                }
+                self.run_action(Action::TnrLaunch)
+                    .map(|_| json!(self.current_tnr))
+                    .inspect_err(|_| self.cache_tnr = cached)
+            }
+            Call::LoadPreset(name) => {
+                match self.presets.iter().position(|(preset, _)| *preset == name) {
+                    Some(i) => {
+                        self.dispatch(Action::LoadPreset(i));
+                        Ok(json!(self.cache_tnr))
+                    }
+                    None => Err(format!("unknown preset {}", name)),
+                }
+            }
+        };
+        self.origin = None;
+        request.respond(result);
+    }
+
+    fn publish_metrics(&self) {
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics,
+            None => return,
+        };
+        let mut out = Exposition::new();
+        out.family("sspa_adc", "gauge", "ADC measurement per channel.");
+        for (name, reg) in CHANNEL_NAMES.iter().zip(self.adc.iter()) {
+            out.sample(&[("channel", name)], reg.value());
+        }
+        out.family("sspa_threshold", "gauge", "Protection threshold value.");
+        for (name, reg) in THRESHOLD_NAMES.iter().zip(self.thresholds.iter()) {
+            out.sample(&[("threshold", name)], reg.value());
+        }
+        out.family("sspa_dac", "gauge", "DAC value per channel.");
+        for (name, value) in CHANNEL_NAMES.iter().zip(self.dac.iter()) {
+            out.sample(&[("channel", name)], *value);
+        }
+        out.family("sspa_offset", "gauge", "Offset value per channel.");
+        for (name, value) in CHANNEL_NAMES.iter().zip(self.offsets.iter()) {
+            out.sample(&[("channel", name)], *value);
+        }
+        out.family("sspa_status_bit", "gauge", "Status register bits.");
+        for (name, set) in STATUS_BITS.iter().zip(bits(&self.status_register)) {
+            out.sample(&[("bit", name)], set as u8);
+        }
+        out.family("sspa_state", "gauge", "Current SSPA state.");
+        for state in (0..=8).map(SSPAState::from_value) {
+            let name = state.to_string();
+            out.sample(&[("state", &name)], (state == self.sspa_state) as u8);
+        }
+        out.family("sspa_version", "gauge", "Firmware version register.");
+        out.sample(&[], self.version_number.value());
+        out.family("sspa_parity_errors_total", "counter", "Parity errors per register.");
+        for info in register_map() {
+            let count = self.parity_errors.get(&info.address).copied().unwrap_or(0);
+            out.sample(&[("group", info.group), ("register", info.name)], count as f64);
+        }
+        out.family("sspa_connection_state", "gauge", "SSH connection state.");
+        for state in [
+            ConnectionState::Connected,
+            ConnectionState::Reconnecting,
+            ConnectionState::Down,
+        ] {
+            let name = state.to_string();
+            out.sample(&[("state", &name)], (state == self.connection_state) as u8);
+        }
+        metrics.publish(out.finish());
+    }
+
+    pub fn pane_mut(&mut self, wid: WidgetId) -> Option<&mut Scrollback> {
+        match wid {
+            WidgetId::Terminal => Some(self.terminal.scrollback_mut()),
+            WidgetId::Ssh => Some(self.ssh.launcher_mut().scrollback_mut()),
+            WidgetId::Log => Some(self.diagnostics.scrollback_mut()),
+            WidgetId::Audit => Some(self.audit.scrollback_mut()),
+            _ => None,
+        }
+    }
+
+    pub fn selected_pane_mut(&mut self) -> Option<&mut Scrollback> {
+        self.pane_mut(self.selected_widget)
+    }
+
+    pub fn search_input(&self) -> Option<&str> {
+        self.search_input.as_deref()
+    }
+
+    pub fn start_search(&mut self) {
+        if self.selected_pane_mut().is_some() {
+            self.search_input = Some(String::new());
+        }
+    }
+
+    pub fn edit_search(&mut self, input: Option<char>) {
+        if let Some(text) = &mut self.search_input {
+            match input {
+                Some(c) => text.push(c),
+                None => {
+                    text.pop();
+                }
+            }
+            let search = Regex::new(text).ok().filter(|_| !text.is_empty());
+            if let Some(pane) = self.selected_pane_mut() {
+                pane.set_search(search);
+            }
+        }
+    }
+
+    pub fn finish_search(&mut self) {
+        self.search_input = None;
+    }
+
+    pub fn clear_search(&mut self) -> bool {
+        self.search_input = None;
+        match self.selected_pane_mut() {
+            Some(pane) if pane.search().is_some() => {
+                pane.set_search(None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn edit_input(&self) -> Option<&str> {
+        self.edit.as_ref().map(|edit| edit.input.as_str())
+    }
+
+    pub fn start_edit(&mut self, target: EditTarget) {
+        let value = match target {
+            EditTarget::Threshold(i) => self.thresholds[i].value(),
+            EditTarget::Dac(i) => self.dac[i],
+            EditTarget::Offset(i) => self.offsets[i],
+            EditTarget::Tnr(i) => self.cache_tnr[i],
+        };
+        self.edit = Some(Edit {
+            target,
+            input: value.to_string(),
+        });
+    }
+
+    pub fn edit_value(&mut self, input: Option<char>) {
+        if let Some(edit) = &mut self.edit {
+            match input {
+                Some(c) if c.is_ascii_hexdigit() || c == 'x' => edit.input.push(c),
+                Some(_) => {}
+                None => {
+                    edit.input.pop();
+                }
+            }
+        }
+    }
+
+    pub fn cancel_edit(&mut self) {
+        self.edit = None;
+    }
+
+    pub fn commit_edit(&mut self) {
+        let edit = match self.edit.take() {
+            Some(edit) => edit,
+            None => return,
+        };
+        let value = match parse_value(&edit.input) {
+            Some(value) => value,
+            None => {
+                self.diagnostics
+                    .sender()
+                    .warning("edit", format!("invalid value {}", edit.input));
+                return;
+            }
+        };
+        self.write_target(edit.target, value);
+    }
+
+    fn write_target(&mut self, target: EditTarget, value: u16) {
+        match target {
+            EditTarget::Threshold(i) => self.dispatch(Action::WriteThreshold(i, value)),
+            EditTarget::Dac(i) => self.dispatch(Action::WriteDac(i, value)),
+            EditTarget::Offset(i) => self.dispatch(Action::WriteOffset(i, value)),
+            EditTarget::Tnr(i) => self.cache_tnr[i] = value,
+        }
+    }
+
+    pub fn palette_input(&self) -> Option<&str> {
+        self.palette.as_ref().map(|palette| palette.input.as_str())
+    }
+
+    pub fn palette_selected(&self) -> usize {
+        self.palette.as_ref().map_or(0, |palette| palette.selected)
+    }
+
+    pub fn palette_commands(&self) -> Vec<Command> {
+        let presets: Vec<String> = self.presets.iter().map(|(name, _)| name.clone()).collect();
+        let layouts: Vec<String> = self.layouts.iter().map(|(name, _)| name.clone()).collect();
+        palette::commands(&presets, &layouts)
+    }
+
+    pub fn palette_matches(&self) -> Vec<(Command, Option<String>)> {
+        let input = match self.palette_input() {
+            Some(input) => input,
+            None => return Vec::new(),
+        };
+        let commands = self.palette_commands();
+        palette::matches(&commands, input)
+            .into_iter()
+            .map(|(i, arg)| (commands[i].clone(), arg))
+            .collect()
+    }
+
+    pub fn edit_palette(&mut self, input: Option<char>) {
+        if let Some(palette) = &mut self.palette {
+            match input {
+                Some(c) => palette.input.push(c),
+                None => {
+                    palette.input.pop();
+                }
+            }
+            palette.selected = 0;
+        }
+    }
+
+    pub fn move_palette(&mut self, up: bool) {
+        let count = self.palette_matches().len();
+        if let Some(palette) = &mut self.palette {
+            palette.selected = if up {
+                palette.selected.saturating_sub(1)
+            } else {
+                (palette.selected + 1).min(count.saturating_sub(1))
+            };
+        }
+    }
+
+    pub fn complete_palette(&mut self) {
+        let selected = self.palette_selected();
+        if let Some((command, _)) = self.palette_matches().into_iter().nth(selected) {
+            if let Some(palette) = &mut self.palette {
+                palette.input = match command.arg {
+                    palette::Arg::None => command.name,
+                    _ => format!("{} ", command.name),
+                };
+                palette.selected = 0;
+            }
+        }
+    }
+
+    pub fn close_palette(&mut self) {
+        self.palette = None;
+    }
+
+    pub fn run_palette(&mut self) {
+        let selected = self.palette_selected();
+        let chosen = self.palette_matches().into_iter().nth(selected);
+        self.palette = None;
+        let (command, arg) = match chosen {
+            Some(chosen) => chosen,
+            None => return,
+        };
+        let diagnostics = self.diagnostics.sender();
+        match (command.kind, arg) {
+            (CommandKind::Action(action), _) => self.dispatch(action),
+            (CommandKind::Write(target), Some(arg)) => match parse_value(&arg) {
+                Some(value) => self.write_target(target, value),
+                None => diagnostics.warning("palette", format!("invalid value {}", arg)),
+            },
+            (CommandKind::Write(_), None) => {
+                diagnostics.warning("palette", format!("{} needs a value", command.name))
+            }
+            (CommandKind::Focus(wid), _) => self.focus(wid),
+            (CommandKind::Export(wid), path) => {
+                let path = path.map(PathBuf::from).unwrap_or_else(|| {
+                    PathBuf::from(format!(
+                        "{}-{}.log",
+                        palette::slug(wid),
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ))
+                });
+                self.export_pane(wid, path);
+            }
+            (CommandKind::Script, Some(path)) => {
+                let path = find_file(PathBuf::from(path), "scripts");
+                self.run_script(&path, false);
+            }
+            (CommandKind::TestPlan, Some(path)) => {
+                let path = find_file(PathBuf::from(path), "plans");
+                self.run_plan(&path, false);
+            }
+            (CommandKind::Snapshot, path) => self.save_snapshot(path.map(PathBuf::from)),
+            (CommandKind::SnapshotDiff, Some(files)) => self.diff_snapshots(&files),
+            (CommandKind::Backup, path) => self.backup_config(path.map(PathBuf::from)),
+            (CommandKind::Restore, Some(path)) => {
+                self.preview_restore(find_file(PathBuf::from(path), "backups"))
+            }
+            (CommandKind::AuditExport, path) => {
+                let path = path.map(PathBuf::from).unwrap_or_else(|| {
+                    PathBuf::from(format!("audit-{}.csv", Local::now().format("%Y%m%d-%H%M%S")))
+                });
+                match self.audit.export(&path) {
+                    Ok(entries) => diagnostics.info(
+                        "audit",
+                        format!("wrote {} entries to {}", entries, path.display()),
+                    ),
+                    Err(e) => diagnostics.error(
+                        "audit",
+                        format!("failed to export {}: {}", path.display(), e),
+                    ),
+                }
+            }
+            (
+                CommandKind::Script
+                | CommandKind::TestPlan
+                | CommandKind::SnapshotDiff
+                | CommandKind::Restore,
+                None,
+            ) => {
+                diagnostics.warning("palette", format!("{} needs a file", command.name))
+            }
+        }
+    }
+
+    fn export_pane(&mut self, wid: WidgetId, path: PathBuf) {
+        let diagnostics = self.diagnostics.sender();
+        if let Some(pane) = self.pane_mut(wid) {
+            match pane.export(&path) {
+                Ok(lines) => diagnostics.info(
+                    "export",
+                    format!("wrote {} lines to {}", lines, path.display()),
+                ),
+                Err(e) => diagnostics.error(
+                    "export",
+                    format!("failed to write {}: {}", path.display(), e),
+                ),
+            }
+        }
+    }
+
+    pub fn dispatch(&mut self, action: Action) {
+        // failures are already reported in the event log
+        let _ = self.run_action(action);
+    }
+
+    fn run_action(&mut self, action: Action) -> Result<(), String> {
+        match action {
+            Action::Quit => {}
+            Action::Help => self.help = !self.help,
+            Action::ToggleZoom => self.zoom = !self.zoom,
+            Action::NextLayout => {
+                let next = (self.layout + 1) % self.layouts.len();
+                self.dispatch(Action::SelectLayout(next));
+            }
+            Action::SelectTheme(name) => {
+                self.theme = Theme::new(name).with_colors(&self.colors).0;
+            }
+            Action::SelectLayout(i) => {
+                if i < self.layouts.len() {
+                    self.layout = i;
+                    self.focus_layout();
+                    self.diagnostics
+                        .sender()
+                        .info("layout", format!("switched to {}", self.layouts[i].0));
+                }
+            }
+            Action::OpenPalette => {
+                self.palette = Some(Palette {
+                    input: String::new(),
+                    selected: 0,
+                })
+            }
+            Action::Connect => self.ssh.connect(),
+            Action::Disconnect => self.ssh.disconnect(),
+            Action::FocusWidget(transition) => self.widget_select(Some(transition)),
+            Action::FocusNext => self.widget_cycle(true),
+            Action::FocusPrevious => self.widget_cycle(false),
+            Action::MoveSelection(transition) => self.element_select(Some(transition)),
+            Action::Activate => {
+                let wid = self.selected_widget;
+                let item = self.list_state[wid as usize].selected();
+                if let Some(action) = item.and_then(|item| item_action(wid, item)) {
+                    self.run_action(action)?;
+                }
+            }
+            Action::EditThreshold(i) => self.start_edit(EditTarget::Threshold(i)),
+            Action::EditDac(i) => self.start_edit(EditTarget::Dac(i)),
+            Action::EditOffset(i) => self.start_edit(EditTarget::Offset(i)),
+            Action::EditTnr(i) => self.start_edit(EditTarget::Tnr(i)),
+            Action::WriteThreshold(i, value) => self.send(DeviceCommand::Write {
+                address: THRESHOLDS_ADDRESS + i as u16,
+                value,
+            })?,
+            Action::WriteDac(i, value) => self.send(DeviceCommand::Write {
+                address: DAC_ADDRESS + i as u16,
+                value,
+            })?,
+            Action::WriteOffset(i, value) => self.send(DeviceCommand::Write {
+                address: OFFSETS_ADDRESS + i as u16,
+                value,
+            })?,
+            Action::ClearDac => {
+                for i in 0..self.dac.len() {
+                    self.run_action(Action::WriteDac(i, 0))?;
+                }
+            }
+            // the unit does not report these back, so only track what it actually received
+            Action::TogglePowerEnable => {
+                self.send(DeviceCommand::PowerEnable(!self.powen))?;
+                self.powen = !self.powen;
+            }
+            Action::TnrLaunch => {
+                self.send(DeviceCommand::TnrLaunch {
+                    period: self.cache_tnr[0],
+                    pulse_width: self.cache_tnr[1],
+                    count: self.cache_tnr[2],
+                })?;
+                self.current_tnr = self.cache_tnr;
+            }
+            Action::TnrStop => {
+                self.send(DeviceCommand::TnrStop)?;
+                self.current_tnr = [0; 3];
+            }
+            Action::TnrSave => {
+                let name = format!("preset_{}", self.presets.len() + 1);
+                self.presets.push((name, self.cache_tnr));
+                self.list_element_count[WidgetId::ExtPresets as usize] = self.presets.len();
+            }
+            Action::LoadPreset(i) => {
+                if let Some((_, preset)) = self.presets.get(i) {
+                    self.cache_tnr = *preset;
+                }
+            }
+            Action::Control(command) => self.send(DeviceCommand::Write {
+                address: CONTROL_ADDRESS,
+                value: self.control_register.value() | command.bit(),
+            })?,
+            Action::ToggleProtection(i) => self.send(DeviceCommand::Write {
+                address: CONTROL_ADDRESS,
+                value: self.control_register.value() ^ (1 << (4 - i)),
+            })?,
+            Action::Compile(step) => {
+                let compile = &self.compile;
+                let script = match step {
+                    CompileStep::Build => compile.build.clone(),
+                    CompileStep::CleanBuild => format!("{} && {}", compile.clean, compile.build),
+                    CompileStep::CleanBuildFlash => format!(
+                        "{} && {} && {}",
+                        compile.clean, compile.build, compile.flash
+                    ),
+                    CompileStep::BuildFlash => format!("{} && {}", compile.build, compile.flash),
+                    CompileStep::Flash => compile.flash.clone(),
+                };
+                let flash = step
+                    .flashes()
+                    .then(|| self.audit_entry("flash", "-".to_string(), script.clone()));
+                if let Some(entry) = &flash {
+                    device::guard(self.read_only, &mut self.audit, entry).inspect_err(|_| {
+                        self.diagnostics
+                            .sender()
+                            .warning("compile", format!("read-only mode, refused {}", step));
+                    })?;
+                }
+                // killing a flash half way can leave the target unbootable
+                if self.flashing() {
+                    self.diagnostics
+                        .sender()
+                        .warning("compile", format!("flash in progress, refused {}", step));
+                    return Err("flash in progress".to_string());
+                }
+                if self.flash.is_some() {
+                    self.terminal.stop();
+                    self.check_flash();
+                }
+                self.compile_step = Some(step);
+                if let Some(mut entry) = flash {
+                    entry.outcome = "started".to_string();
+                    self.audit.record(entry.clone());
+                    self.flash = Some(entry);
+                }
+                self.terminal.launch_shell(&script);
+            }
+            Action::HardReset => self.send(DeviceCommand::HardReset)?,
+            Action::AbortScript => self.abort_script(),
+            Action::MouseClick { column, row } => self.click(column, row),
+            Action::MouseScroll { column, row, up } => self.wheel(column, row, up),
+            Action::StartSearch => self.start_search(),
+            action => {
+                if let Some(pane) = self.selected_pane_mut() {
+                    match action {
+                        Action::ScrollUp(lines) => pane.scroll_up(lines),
+                        Action::ScrollDown(lines) => pane.scroll_down(lines),
+                        Action::PageUp => pane.page_up(),
+                        Action::PageDown => pane.page_down(),
+                        Action::ScrollTop => pane.scroll_to_top(),
+                        Action::ToggleFollow => pane.toggle_follow(),
+                        Action::ToggleTimestamps => pane.toggle_timestamps(),
+                        Action::CycleFilter => pane.cycle_filter(),
+                        Action::ToggleHex => pane.toggle_hex(),
+                        Action::NextMatch => pane.next_match(),
+                        Action::PreviousMatch => pane.previous_match(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear_areas(&mut self) {
+        self.areas = Default::default();
+        self.tabs.clear();
+    }
+
+    pub fn set_tab(&mut self, wid: WidgetId, rect: Rect) {
+        self.tabs.push((rect, wid));
+    }
+
+    pub fn layout(&self) -> Option<&LayoutNode> {
+        self.layouts[self.layout].1.as_ref()
+    }
+
+    pub fn layout_name(&self) -> &str {
+        &self.layouts[self.layout].0
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn zoom(&self) -> bool {
+        self.zoom
+    }
+
+    pub fn set_tabbed(&mut self, tabbed: bool) {
+        self.tabbed = tabbed;
+    }
+
+    pub fn set_area(&mut self, wid: WidgetId, rect: Rect, item_heights: Vec<usize>, offset: usize) {
+        self.areas[wid as usize] = Some(WidgetArea {
+            rect,
+            item_heights,
+            offset,
+        });
+    }
+
+    pub fn list_offset(&self, wid: WidgetId) -> usize {
+        self.areas[wid as usize]
+            .as_ref()
+            .map_or(0, |area| area.offset)
+    }
+
+    fn widget_at(&self, column: u16, row: u16) -> Option<WidgetId> {
+        let contains = |rect: Rect| {
+            column >= rect.x
+                && column < rect.x + rect.width
+                && row >= rect.y
+                && row < rect.y + rect.height
+        };
+        WIDGETS
+            .into_iter()
+            .find(|wid| {
+                self.areas[*wid as usize]
+                    .as_ref()
+                    .is_some_and(|area| contains(area.rect))
+            })
+            .or_else(|| {
+                self.tabs
+                    .iter()
+                    .find(|(rect, _)| contains(*rect))
+                    .map(|(_, wid)| *wid)
+            })
+    }
+
+    fn item_at(&self, wid: WidgetId, row: u16) -> Option<usize> {
+        let area = self.areas[wid as usize].as_ref()?;
+        let mut top = area.rect.y + 1;
+        if row < top {
+            return None;
+        }
+        for (item, height) in area.item_heights.iter().enumerate().skip(area.offset) {
+            top += *height as u16;
+            if row < top {
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    // a widget left out of the layout cannot keep the focus
+    fn focus_layout(&mut self) {
+        let panels = match self.layout() {
+            Some(tree) => tree.panels(),
+            None => return,
+        };
+        if panels.contains(&chunk_index(self.selected_widget)) {
+            return;
+        }
+        let first = panels
+            .into_iter()
+            .find_map(|index| WIDGETS.into_iter().find(|wid| chunk_index(*wid) == index));
+        if let Some(wid) = first {
+            self.focus(wid);
+        }
+    }
+
+    pub fn focus(&mut self, wid: WidgetId) {
+        if self.selected_widget != wid {
+            self.selected_widget = wid;
+            self.search_input = None;
+            if self.list_element_count[wid as usize] > 0 {
+                self.list_state[wid as usize].select(Some(0));
+            }
+        }
+    }
+
+    fn click(&mut self, column: u16, row: u16) {
+        let wid = match self.widget_at(column, row) {
+            Some(wid) => wid,
+            None => return,
+        };
+        self.focus(wid);
+        let item = match self.item_at(wid, row) {
+            Some(item) => item,
+            None => return,
+        };
+        self.list_state[wid as usize].select(Some(item));
+        let now = Instant::now();
+        let double = self.last_click.is_some_and(|(time, last_wid, last_item)| {
+            now.duration_since(time) < DOUBLE_CLICK && last_wid == wid && last_item == item
+        });
+        if double {
+            self.last_click = None;
+            self.dispatch(Action::Activate);
+        } else {
+            self.last_click = Some((now, wid, item));
+        }
+    }
+
+    fn wheel(&mut self, column: u16, row: u16, up: bool) {
+        let wid = match self.widget_at(column, row) {
+            Some(wid) => wid,
+            None => return,
+        };
+        if let Some(pane) = self.pane_mut(wid) {
+            if up {
+                pane.scroll_up(3);
+            } else {
+                pane.scroll_down(3);
+            }
+            return;
+        }
+        self.focus(wid);
+        self.element_select(Some(if up {
+            StateTransition::Up
+        } else {
+            StateTransition::Down
+        }));
+    }
+
+    fn flashing(&self) -> bool {
+        self.terminal.is_running() && self.compile_step.is_some_and(|step| step.flashes())
+    }
+
+    fn send(&mut self, command: DeviceCommand) -> Result<(), String> {
+        let line = command.to_line();
+        let diagnostics = self.diagnostics.sender();
+        let (operation, old, new) = describe_command(command, &self.registers);
+        let mut entry = self.audit_entry(&operation, old, new);
+        if let Err(e) = device::guard(self.read_only, &mut self.audit, &entry) {
+            diagnostics.error("device", format!("{}, dropped {}", e, line));
+            return Err(e);
+        }
+        let result = match self.ssh.send(&line) {
+            true => Ok(()),
+            false => Err("not connected".to_string()),
+        };
+        match &result {
+            Ok(()) => diagnostics.info("device", format!("sent {}", line)),
+            Err(e) => diagnostics.error("device", format!("{}, dropped {}", e, line)),
+        }
+        entry.outcome = match &result {
+            Ok(()) => "sent".to_string(),
+            Err(e) => e.clone(),
+        };
+        match command {
+            // the unit confirms a write by reporting the new value, control writes are commands
+            DeviceCommand::Write { address, value }
+                if result.is_ok() && address != CONTROL_ADDRESS =>
+            {
+                let deadline = Instant::now() + READBACK_TIMEOUT;
+                self.pending_writes.push((address, value, deadline, entry));
+            }
+            _ => self.audit.record(entry),
+        }
+        result
+    }
+
+    fn audit_entry(&self, operation: &str, old: String, new: String) -> AuditEntry {
+        let mut entry = unit_entry(&self.audit, &self.registers, operation, old, new);
+        if let Some(origin) = &self.origin {
+            entry.origin = origin.clone();
+        }
+        entry
+    }
+
+    fn confirm_write(&mut self, address: u16, value: u16) {
+        let (confirmed, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_writes)
+            .into_iter()
+            .partition(|(a, v, _, _)| *a == address && *v == value);
+        self.pending_writes = pending;
+        for (_, _, _, mut entry) in confirmed {
+            entry.outcome = "verified".to_string();
+            self.audit.record(entry);
+        }
+    }
+
+    fn expire_writes(&mut self, now: Option<Instant>) {
+        let (expired, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_writes)
+            .into_iter()
+            .partition(|(_, _, deadline, _)| now.is_none_or(|now| *deadline <= now));
+        self.pending_writes = pending;
+        for (_, _, _, mut entry) in expired {
+            entry.outcome = "not confirmed".to_string();
+            self.audit.record(entry);
+        }
+    }
+
+    pub fn writes_pending(&self) -> bool {
+        !self.pending_writes.is_empty()
+    }
+
+    // nothing reads the unit back after exit, whatever is still pending stays unconfirmed
+    pub fn close_writes(&mut self) {
+        self.expire_writes(None);
+    }
+
+    fn check_flash(&mut self) -> bool {
+        if self.terminal.is_running() {
+            return false;
+        }
+        let started = match self.flash.take() {
+            Some(started) => started,
+            None => return false,
+        };
+        let mut entry = self.audit.entry(started.operation, started.old, started.new);
+        entry.serial_number = started.serial_number;
+        entry.origin = started.origin;
+        entry.outcome = match self.terminal.exit_status() {
+            Some(status) if status.success() => "ok".to_string(),
+            Some(status) => format!("failed, {}", status),
+            None => "interrupted".to_string(),
+        };
+        self.audit.record(entry);
+        true
+    }
+
+    pub fn apply_register(&mut self, address: u16, raw: u16) {
+        let reg = Register::new(raw);
+        match reg.state() {
+            RegisterState::ParityError => *self.parity_errors.entry(address).or_insert(0) += 1,
+            _ => self.confirm_write(address, reg.value()),
+        }
+        if self.registers.get(&address).map(Register::raw) != Some(raw) {
+            if let Some(info) = register_map().iter().find(|info| info.address == address) {
+                self.publish_event("register", register_json(info, &reg));
+            }
+        }
+        self.registers.insert(address, reg);
+        match address {
+            STATUS_ADDRESS => self.status_register = reg,
+            STATE_ADDRESS => {
+                let state = SSPAState::from_value(reg.value());
+                if state != self.sspa_state {
+                    self.publish_event("state", json!(state.to_string()));
+                }
+                self.sspa_state = state;
+            }
+            VERSION_ADDRESS => self.version_number = reg,
+            CONTROL_ADDRESS => self.control_register = reg,
+            a if (ADC_ADDRESS..ADC_ADDRESS + 8).contains(&a) => {
+                self.adc[(a - ADC_ADDRESS) as usize] = reg
+            }
+            a if (THRESHOLDS_ADDRESS..THRESHOLDS_ADDRESS + 10).contains(&a) => {
+                self.thresholds[(a - THRESHOLDS_ADDRESS) as usize] = reg
+            }
+            a if (DAC_ADDRESS..DAC_ADDRESS + 8).contains(&a) => {
+                self.dac[(a - DAC_ADDRESS) as usize] = reg.value()
+            }
+            a if (OFFSETS_ADDRESS..OFFSETS_ADDRESS + 8).contains(&a) => {
+                self.offsets[(a - OFFSETS_ADDRESS) as usize] = reg.value()
+            }
+            _ => {}
+        }
+    }
+
     pub fn widget_select(&mut self, transition: Option<StateTransition>) {
-        if let Some(state_transition) = transition {
-            self.selected_widget =
-                self.widget_transition[self.selected_widget as usize][state_transition as usize];
-            self.list_state[self.selected_widget as usize].select(Some(0));
+        let transition = match transition {
+            Some(transition) => transition,
+            None => return,
+        };
+        if self.tabbed {
+            let forward = matches!(transition, StateTransition::Right | StateTransition::Down);
+            self.widget_cycle(forward);
+            return;
+        }
+        let current = match &self.areas[self.selected_widget as usize] {
+            Some(area) => area.rect,
+            None => {
+                self.widget_cycle(true);
+                return;
+            }
+        };
+        let target = WIDGETS
+            .into_iter()
+            .filter(|wid| *wid != self.selected_widget)
+            .filter_map(|wid| {
+                let rect = self.areas[wid as usize].as_ref()?.rect;
+                direction_distance(current, rect, transition).map(|distance| (distance, wid))
+            })
+            .min_by_key(|(distance, _)| *distance);
+        if let Some((_, wid)) = target {
+            self.focus(wid);
+        }
+    }
+
+    pub fn widget_cycle(&mut self, forward: bool) {
+        let order: Vec<WidgetId> = if self.tabbed {
+            WIDGETS.to_vec()
+        } else {
+            let mut areas: Vec<(Rect, WidgetId)> = WIDGETS
+                .into_iter()
+                .filter_map(|wid| Some((self.areas[wid as usize].as_ref()?.rect, wid)))
+                .collect();
+            areas.sort_by_key(|(rect, _)| (rect.x, rect.y));
+            areas.into_iter().map(|(_, wid)| wid).collect()
+        };
+        if order.is_empty() {
+            return;
         }
+        let count = order.len();
+        let next = match order.iter().position(|wid| *wid == self.selected_widget) {
+            Some(index) if forward => (index + 1) % count,
+            Some(index) => (index + count - 1) % count,
+            None => 0,
+        };
+        self.focus(order[next]);
     }
 
     pub fn is_widget_selected(&self, wid: WidgetId) -> bool {
@@ -191,7 +1683,7 @@ impl StateKeeper {
                 StateTransition::Down => {
                     for e in &mut self.list_state {
                         if let Some(n) = e.selected() {
-                            let n = (n+1).clamp(0, self.list_element_count[self.selected_widget as usize] - 1);
+                            let n = (n+1).clamp(0, self.list_element_count[self.selected_widget as usize].saturating_sub(1));
                             e.select(Some(n));
                         }
                     }
@@ -200,5 +1692,290 @@ impl StateKeeper {
             }
         }
     }
+}
+
+fn direction_distance(from: Rect, to: Rect, transition: StateTransition) -> Option<(u32, u16)> {
+    let (from_start, from_end, to_start, to_end, gap) = match transition {
+        StateTransition::Left | StateTransition::Right => {
+            (from.y, from.bottom(), to.y, to.bottom(), match transition {
+                StateTransition::Left => from.x.checked_sub(to.right()),
+                _ => to.x.checked_sub(from.right()),
+            })
+        }
+        StateTransition::Up | StateTransition::Down => {
+            (from.x, from.right(), to.x, to.right(), match transition {
+                StateTransition::Up => from.y.checked_sub(to.bottom()),
+                _ => to.y.checked_sub(from.bottom()),
+            })
+        }
+    };
+    let offset = from_start.saturating_sub(to_end) + to_start.saturating_sub(from_end);
+    let centre = (from_start + from_end).abs_diff(to_start + to_end);
+    Some((gap? as u32 + offset as u32 * 2, centre))
+}
+
+fn find_file(path: PathBuf, dir: &str) -> PathBuf {
+    match config_dir().map(|config| config.join(dir).join(&path)) {
+        Some(file) if !path.exists() && file.exists() => file,
+        _ => path,
+    }
+}
 
+fn parse_value(input: &str) -> Option<u16> {
+    let value = match input.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => input.parse().ok()?,
+    };
+    (value <= 0x7FFF).then_some(value)
+}
+
+fn run_limit_command(command: &str, name: &str, value: f64, diagnostics: DiagnosticsSender) {
+    let mut process = Process::new("sh");
+    process
+        .arg("-c")
+        .arg(command)
+        .env("SSPA_LIMIT", name)
+        .env("SSPA_VALUE", value.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let command = command.to_string();
+    tokio::spawn(async move {
+        match process.status().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => diagnostics.warning("limits", format!("{} exited with {}", command, status)),
+            Err(e) => diagnostics.error("limits", format!("failed to run {}: {}", command, e)),
+        }
+    });
+}
+
+pub fn describe_command(
+    command: DeviceCommand,
+    registers: &BTreeMap<u16, Register>,
+) -> (String, String, String) {
+    let none = || "-".to_string();
+    match command {
+        DeviceCommand::Write { address, value } => {
+            let old = registers.get(&address).map(Register::value);
+            let info = register_map().into_iter().find(|info| info.address == address);
+            match info {
+                Some(_) if address == CONTROL_ADDRESS => (
+                    format!("write control {}", control_change(old.unwrap_or(0), value)),
+                    old.map_or_else(none, |old| format!("0x{:04x}", old)),
+                    format!("0x{:04x}", value),
+                ),
+                Some(info) => (
+                    format!("write {}.{}", info.group, info.name),
+                    old.map_or_else(none, |old| old.to_string()),
+                    value.to_string(),
+                ),
+                None => (
+                    format!("write 0x{:02x}", address),
+                    old.map_or_else(none, |old| old.to_string()),
+                    value.to_string(),
+                ),
+            }
+        }
+        DeviceCommand::HardReset => ("hard reset".to_string(), none(), none()),
+        DeviceCommand::PowerEnable(on) => {
+            ("power enable".to_string(), (!on).to_string(), on.to_string())
+        }
+        DeviceCommand::TnrLaunch {
+            period,
+            pulse_width,
+            count,
+        } => (
+            "tnr launch".to_string(),
+            none(),
+            format!("{} {} {}", period, pulse_width, count),
+        ),
+        DeviceCommand::TnrStop => ("tnr stop".to_string(), none(), none()),
+    }
+}
+
+// audit entries for the unit carry its serial number, the CLI builds them the same way
+pub fn unit_entry(
+    audit: &Audit,
+    registers: &BTreeMap<u16, Register>,
+    operation: &str,
+    old: String,
+    new: String,
+) -> AuditEntry {
+    let mut entry = audit.entry(operation, old, new);
+    entry.serial_number = registers.get(&SERIAL_NUMBER_ADDRESS).map(Register::value);
+    entry
+}
+
+fn control_change(old: u16, new: u16) -> String {
+    let changes: Vec<String> = CONTROL_BITS
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| !name.is_empty())
+        .filter_map(|(i, name)| {
+            let bit = 1 << (14 - i);
+            match (old & bit != 0, new & bit != 0) {
+                (false, true) => Some(format!("+{}", name)),
+                (true, false) => Some(format!("-{}", name)),
+                _ => None,
+            }
+        })
+        .collect();
+    match changes.is_empty() {
+        true => "(no change)".to_string(),
+        false => format!("({})", changes.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::ControlCommand;
+    use std::{env, fs, process};
+
+    fn state(read_only: bool) -> (StateKeeper, PathBuf) {
+        let name = format!("sspa_tui_{}_{}_audit.log", process::id(), read_only);
+        let audit_file = env::temp_dir().join(name);
+        let _ = fs::remove_file(&audit_file);
+        let config = Config {
+            read_only,
+            ssh_command: "cat".to_string(),
+            audit_file: Some(audit_file.clone()),
+            compile: CompileConfig {
+                build: "true".to_string(),
+                ..CompileConfig::default()
+            },
+            ..Config::default()
+        };
+        (StateKeeper::headless(&config), audit_file)
+    }
+
+    #[tokio::test]
+    async fn read_only_refuses_every_device_command() {
+        let (mut state, audit_file) = state(true);
+        let writes = [
+            Action::WriteDac(0, 5),
+            Action::ClearDac,
+            Action::TogglePowerEnable,
+            Action::TnrLaunch,
+            Action::TnrStop,
+            Action::Control(ControlCommand::AlarmsReset),
+            Action::ToggleProtection(0),
+            Action::HardReset,
+            Action::Compile(CompileStep::Flash),
+        ];
+        for action in writes {
+            let result = state.run_action(action);
+            assert_eq!(result, Err("read-only mode".to_string()), "{}", action);
+        }
+        assert!(state.ext_signals().0);
+        assert_eq!(state.ext_signals().1, [0; 3]);
+        assert!(state.run_action(Action::Compile(CompileStep::Build)).is_ok());
+        let audit = fs::read_to_string(&audit_file).unwrap();
+        fs::remove_file(&audit_file).unwrap();
+        assert_eq!(audit.lines().count(), writes.len());
+        assert!(audit.lines().last().unwrap().contains("\"operation\":\"flash\""));
+        assert!(audit.lines().all(|line| line.contains("\"outcome\":\"read-only mode\"")));
+    }
+
+    #[test]
+    fn writes_outside_read_only_mode_are_refused_only_when_disconnected() {
+        let (mut state, audit_file) = state(false);
+        let result = state.run_action(Action::WriteDac(0, 5));
+        let audit = fs::read_to_string(&audit_file).unwrap();
+        fs::remove_file(&audit_file).unwrap();
+        assert_eq!(result, Err("not connected".to_string()));
+        assert!(audit.contains("\"outcome\":\"not connected\""));
+    }
+
+    #[test]
+    fn layout_switch_moves_focus_into_the_layout() {
+        let (mut state, _) = state(false);
+        let firmware = state.layouts.iter().position(|(name, _)| name == "firmware").unwrap();
+        state.focus(WidgetId::Ext);
+        state.dispatch(Action::SelectLayout(firmware));
+        assert_eq!(state.selected_widget, WidgetId::Compile);
+        state.focus(WidgetId::Terminal);
+        state.dispatch(Action::SelectLayout(firmware));
+        assert_eq!(state.selected_widget, WidgetId::Terminal);
+        state.dispatch(Action::SelectLayout(0));
+        assert_eq!(state.selected_widget, WidgetId::Terminal);
+    }
+
+    #[test]
+    fn click_focuses_the_widget_and_selects_the_item() {
+        let (mut state, _) = state(false);
+        state.set_area(WidgetId::Registers, Rect::new(0, 0, 40, 12), vec![1; 10], 0);
+        state.set_area(WidgetId::Dac, Rect::new(40, 0, 40, 12), vec![1; 9], 2);
+        state.dispatch(Action::MouseClick { column: 45, row: 3 });
+        assert_eq!(state.selected_widget, WidgetId::Dac);
+        assert_eq!(state.list_state[WidgetId::Dac as usize].selected(), Some(4));
+        state.dispatch(Action::MouseClick { column: 10, row: 0 });
+        assert_eq!(state.selected_widget, WidgetId::Registers);
+        assert_eq!(state.list_state[WidgetId::Registers as usize].selected(), Some(0));
+        state.dispatch(Action::MouseClick { column: 90, row: 3 });
+        assert_eq!(state.selected_widget, WidgetId::Registers);
+        assert!(state.edit.is_none());
+    }
+
+    #[test]
+    fn double_click_activates_the_item() {
+        let (mut state, _) = state(false);
+        state.set_area(WidgetId::Registers, Rect::new(0, 0, 40, 12), vec![1; 10], 0);
+        state.dispatch(Action::MouseClick { column: 5, row: 3 });
+        assert!(state.edit.is_none());
+        state.dispatch(Action::MouseClick { column: 5, row: 4 });
+        assert!(state.edit.is_none());
+        state.dispatch(Action::MouseClick { column: 5, row: 4 });
+        assert!(matches!(state.edit.as_ref().map(|edit| edit.target), Some(EditTarget::Threshold(3))));
+    }
+
+    #[test]
+    fn direction_distance_prefers_the_nearest_aligned_widget() {
+        let from = Rect::new(20, 10, 20, 10);
+        let right = |x, y| direction_distance(from, Rect::new(x, y, 20, 10), StateTransition::Right);
+        assert_eq!(right(0, 10), None);
+        assert_eq!(right(30, 10), None);
+        assert!(right(40, 10) < right(50, 10));
+        assert!(right(40, 10) < right(40, 15));
+        assert!(right(40, 15) < right(40, 25));
+        assert!(right(40, 8) < right(40, 15));
+        let up = direction_distance(from, Rect::new(20, 0, 20, 10), StateTransition::Up);
+        let down = direction_distance(from, Rect::new(20, 0, 20, 10), StateTransition::Down);
+        assert_eq!(up, Some((0, 0)));
+        assert_eq!(down, None);
+    }
+
+    #[test]
+    fn focus_moves_to_the_nearest_widget_in_the_direction() {
+        let (mut state, _) = state(false);
+        state.set_area(WidgetId::Registers, Rect::new(0, 0, 40, 20), vec![], 0);
+        state.set_area(WidgetId::Dac, Rect::new(40, 0, 40, 10), vec![], 0);
+        state.set_area(WidgetId::Offsets, Rect::new(40, 10, 40, 10), vec![], 0);
+        state.set_area(WidgetId::Control, Rect::new(80, 0, 40, 20), vec![], 0);
+        state.focus(WidgetId::Offsets);
+        state.dispatch(Action::FocusWidget(StateTransition::Up));
+        assert_eq!(state.selected_widget, WidgetId::Dac);
+        state.dispatch(Action::FocusWidget(StateTransition::Right));
+        assert_eq!(state.selected_widget, WidgetId::Control);
+        state.dispatch(Action::FocusWidget(StateTransition::Right));
+        assert_eq!(state.selected_widget, WidgetId::Control);
+        state.dispatch(Action::FocusWidget(StateTransition::Left));
+        assert_eq!(state.selected_widget, WidgetId::Dac);
+        state.dispatch(Action::FocusWidget(StateTransition::Left));
+        assert_eq!(state.selected_widget, WidgetId::Registers);
+    }
+
+    #[test]
+    fn refused_api_launch_keeps_the_cached_tnr() {
+        let (mut state, audit_file) = state(true);
+        state.connection_state = ConnectionState::Connected;
+        state.cache_tnr = [100, 10, 3];
+        let params = json!({ "period": 200, "pulse_width": 20, "count": 6 });
+        let (request, mut reply) = Request::new("api", "client 1".to_string(), "tnr_launch", params);
+        state.handle_request(request);
+        fs::remove_file(&audit_file).unwrap();
+        assert_eq!(reply.try_recv().unwrap(), Err("read-only mode".to_string()));
+        assert_eq!(state.cache_tnr, [100, 10, 3]);
+        assert_eq!(state.current_tnr, [0; 3]);
+    }
 }
@@ -0,0 +1,33 @@
+pub fn parse_line(line: &str) -> Option<(u16, u16)> {
+    let (address, value) = line.trim().split_once(':')?;
+    Some((parse_hex(address)?, parse_hex(value)?))
+}
+
+fn parse_hex(word: &str) -> Option<u16> {
+    let word = word.trim();
+    let word = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix("0X"))
+        .unwrap_or(word);
+    u16::from_str_radix(word, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_address_and_value() {
+        assert_eq!(parse_line("0x22: 0x8400"), Some((0x22, 0x8400)));
+        assert_eq!(parse_line("  0X1a:0xffff\r\n"), Some((0x1a, 0xffff)));
+        assert_eq!(parse_line("17: 64"), Some((0x17, 0x64)));
+    }
+
+    #[test]
+    fn rejects_other_output() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("Welcome to the unit"), None);
+        assert_eq!(parse_line("0x22: 0x18400"), None);
+        assert_eq!(parse_line("0x22 0x8400"), None);
+    }
+}
@@ -0,0 +1,264 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tui::{
+    style::{Color, Modifier, Style},
+    widgets::BorderType,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    ColorBlind,
+    Monochrome,
+}
+
+pub const THEMES: [(&str, ThemeName); 5] = [
+    ("dark", ThemeName::Dark),
+    ("light", ThemeName::Light),
+    ("high-contrast", ThemeName::HighContrast),
+    ("color-blind", ThemeName::ColorBlind),
+    ("monochrome", ThemeName::Monochrome),
+];
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub text: Style,
+    pub inactive: Style,
+    pub active: Style,
+    pub ok: Style,
+    pub info: Style,
+    pub warning: Style,
+    pub error: Style,
+    pub focus: Style,
+    pub focus_border: BorderType,
+    pub accent: Style,
+    pub muted: Style,
+    pub stderr: Style,
+    pub search_match: Style,
+    pub current_match: Style,
+    pub symbols: bool,
+}
+
+fn fg(color: Color) -> Style {
+    Style::default().fg(color)
+}
+
+fn modifier(modifier: Modifier) -> Style {
+    Style::default().add_modifier(modifier)
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Theme {
+        match name {
+            ThemeName::Dark => Theme {
+                text: fg(Color::White),
+                inactive: fg(Color::DarkGray),
+                active: fg(Color::White),
+                ok: fg(Color::Green),
+                info: fg(Color::Blue),
+                warning: fg(Color::Yellow),
+                error: fg(Color::Red),
+                focus: fg(Color::Green),
+                focus_border: BorderType::Plain,
+                accent: fg(Color::Yellow),
+                muted: fg(Color::DarkGray),
+                stderr: fg(Color::LightRed),
+                search_match: fg(Color::Black).bg(Color::Yellow),
+                current_match: fg(Color::Black).bg(Color::LightCyan),
+                symbols: false,
+            },
+            ThemeName::Light => Theme {
+                text: fg(Color::Black),
+                inactive: fg(Color::Gray),
+                active: fg(Color::Black),
+                ok: fg(Color::Rgb(0, 128, 0)),
+                info: fg(Color::Blue),
+                warning: fg(Color::Rgb(176, 96, 0)),
+                error: fg(Color::Red),
+                focus: fg(Color::Blue),
+                focus_border: BorderType::Plain,
+                accent: fg(Color::Magenta),
+                muted: fg(Color::Gray),
+                stderr: fg(Color::Red),
+                search_match: fg(Color::Black).bg(Color::Yellow),
+                current_match: fg(Color::Black).bg(Color::Cyan),
+                symbols: false,
+            },
+            ThemeName::HighContrast => Theme {
+                text: fg(Color::White),
+                inactive: fg(Color::Gray),
+                active: fg(Color::White).add_modifier(Modifier::BOLD),
+                ok: fg(Color::LightGreen).add_modifier(Modifier::BOLD),
+                info: fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+                warning: fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                error: fg(Color::LightRed).add_modifier(Modifier::BOLD),
+                focus: fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+                focus_border: BorderType::Thick,
+                accent: fg(Color::LightYellow),
+                muted: fg(Color::Gray),
+                stderr: fg(Color::LightRed),
+                search_match: fg(Color::Black).bg(Color::LightYellow),
+                current_match: fg(Color::Black).bg(Color::White),
+                symbols: false,
+            },
+            ThemeName::ColorBlind => Theme {
+                text: fg(Color::White),
+                inactive: fg(Color::DarkGray),
+                active: fg(Color::White),
+                ok: fg(Color::Rgb(86, 180, 233)),
+                info: fg(Color::Rgb(0, 114, 178)),
+                warning: fg(Color::Rgb(240, 228, 66)),
+                error: fg(Color::Rgb(230, 159, 0)).add_modifier(Modifier::BOLD),
+                focus: fg(Color::Rgb(86, 180, 233)).add_modifier(Modifier::BOLD),
+                focus_border: BorderType::Double,
+                accent: fg(Color::Rgb(240, 228, 66)),
+                muted: fg(Color::DarkGray),
+                stderr: fg(Color::Rgb(204, 121, 167)),
+                search_match: fg(Color::Black).bg(Color::Rgb(240, 228, 66)),
+                current_match: fg(Color::Black).bg(Color::Rgb(86, 180, 233)),
+                symbols: true,
+            },
+            ThemeName::Monochrome => Theme {
+                text: Style::default(),
+                inactive: modifier(Modifier::DIM),
+                active: modifier(Modifier::REVERSED),
+                ok: modifier(Modifier::BOLD),
+                info: modifier(Modifier::ITALIC),
+                warning: modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                error: modifier(Modifier::BOLD | Modifier::REVERSED),
+                focus: modifier(Modifier::BOLD),
+                focus_border: BorderType::Double,
+                accent: modifier(Modifier::BOLD),
+                muted: modifier(Modifier::DIM),
+                stderr: modifier(Modifier::ITALIC),
+                search_match: modifier(Modifier::UNDERLINED),
+                current_match: modifier(Modifier::REVERSED),
+                symbols: true,
+            },
+        }
+    }
+
+    pub fn with_colors(mut self, colors: &BTreeMap<String, String>) -> (Theme, Vec<String>) {
+        let mut problems = Vec::new();
+        for (name, value) in colors {
+            let color = match parse_color(value) {
+                Some(color) => color,
+                None => {
+                    problems.push(format!("invalid colour {} for {}", value, name));
+                    continue;
+                }
+            };
+            let style = match name.as_str() {
+                "text" => &mut self.text,
+                "inactive" => &mut self.inactive,
+                "active" => &mut self.active,
+                "ok" => &mut self.ok,
+                "info" => &mut self.info,
+                "warning" => &mut self.warning,
+                "error" => &mut self.error,
+                "focus" => &mut self.focus,
+                "accent" => &mut self.accent,
+                "muted" => &mut self.muted,
+                "stderr" => &mut self.stderr,
+                _ => {
+                    problems.push(format!("unknown theme colour {}", name));
+                    continue;
+                }
+            };
+            *style = style.fg(color);
+        }
+        (self, problems)
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+    }
+    if let Ok(index) = value.parse() {
+        return Some(Color::Indexed(index));
+    }
+    let color = match value.to_lowercase().replace(['-', '_'], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn overrides_replace_the_foreground_and_keep_the_modifiers() {
+        let base = Theme::new(ThemeName::HighContrast);
+        let (theme, problems) =
+            base.with_colors(&colors(&[("error", "#ff8000"), ("muted", "light-blue")]));
+        assert!(problems.is_empty());
+        assert_eq!(theme.error, base.error.fg(Color::Rgb(255, 128, 0)));
+        assert!(theme.error.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(theme.muted, fg(Color::LightBlue));
+        assert_eq!(theme.ok, base.ok);
+        assert_eq!(theme.focus_border, base.focus_border);
+    }
+
+    #[test]
+    fn bad_overrides_are_reported_and_skipped() {
+        let base = Theme::new(ThemeName::Dark);
+        let (theme, problems) = base.with_colors(&colors(&[
+            ("border", "red"),
+            ("ok", "#12345"),
+            ("text", "chartreuse"),
+            ("warning", "208"),
+        ]));
+        assert_eq!(
+            problems,
+            [
+                "unknown theme colour border",
+                "invalid colour #12345 for ok",
+                "invalid colour chartreuse for text",
+            ]
+        );
+        assert_eq!(theme.ok, base.ok);
+        assert_eq!(theme.text, base.text);
+        assert_eq!(theme.warning, fg(Color::Indexed(208)));
+    }
+
+    #[test]
+    fn parse_color_accepts_names_indices_and_hex() {
+        assert_eq!(parse_color("Dark_Grey"), Some(Color::DarkGray));
+        assert_eq!(parse_color("default"), Some(Color::Reset));
+        assert_eq!(parse_color("#0a0B0c"), Some(Color::Rgb(10, 11, 12)));
+        assert_eq!(parse_color("255"), Some(Color::Indexed(255)));
+        assert_eq!(parse_color("256"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+    }
+}
@@ -1,17 +1,28 @@
 use tui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, Wrap},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table, Wrap},
     Frame,
 };
 
-use crate::{color::ColorTrait, launcher::Launcher};
-use crate::sspa::{bits, SSPAState};
-use crate::state::StateKeeper;
+use crate::{
+    action::item_action,
+    color::ColorTrait,
+    config::user_name,
+    layout::LayoutNode,
+    theme::Theme,
+    scrollback::{Line, Scrollback, Stream},
+};
+use crate::sspa::{bits, Register, STATUS_BITS};
+use crate::state::{StateKeeper, WIDGETS};
 
-#[derive(Clone, Copy, PartialEq)]
+pub const WIDGET_COUNT: usize = 12;
+
+type Draw<B> = fn(Rect, &mut Frame<B>, &mut StateKeeper);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WidgetId {
     Registers,
     HardReset,
@@ -21,148 +32,387 @@ pub enum WidgetId {
     Dac,
     Offsets,
     Control,
+    Ssh,
+    Terminal,
+    Log,
+    Audit,
+}
+
+impl WidgetId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WidgetId::Registers => "Registers",
+            WidgetId::HardReset => "Hard Reset",
+            WidgetId::Ext => "Ext Signals",
+            WidgetId::ExtPresets => "Presets",
+            WidgetId::Compile => "Compile",
+            WidgetId::Dac => "DAC",
+            WidgetId::Offsets => "Offsets",
+            WidgetId::Control => "Control",
+            WidgetId::Ssh => "SSH",
+            WidgetId::Terminal => "Terminal",
+            WidgetId::Log => "Event Log",
+            WidgetId::Audit => "Audit Log",
+        }
+    }
+}
+
+const MIN_WIDTH: u16 = 50;
+const MIN_HEIGHT: u16 = 12;
+const STATUS_CELL: u16 = 19;
+const STATUS_SPACING: u16 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum LayoutMode {
+    Full,
+    Medium,
+    Compact,
+}
+
+fn layout_mode(area: Rect) -> LayoutMode {
+    if area.width >= 160 && area.height >= 45 {
+        LayoutMode::Full
+    } else if area.width >= 120 && area.height >= 30 {
+        LayoutMode::Medium
+    } else {
+        LayoutMode::Compact
+    }
+}
+
+fn status_columns(width: u16) -> usize {
+    (width.saturating_sub(2) + STATUS_SPACING) as usize / (STATUS_CELL + STATUS_SPACING) as usize
+}
+
+fn status_single_row(width: u16) -> bool {
+    let labels: usize = STATUS_BITS.iter().map(|label| label.len()).sum();
+    let spacing = STATUS_SPACING as usize * (STATUS_BITS.len() - 1);
+    width.saturating_sub(2) as usize >= labels + spacing
+}
+
+fn status_height(width: u16) -> u16 {
+    if status_single_row(width) {
+        return 3;
+    }
+    let columns = status_columns(width).max(1);
+    STATUS_BITS.len().div_ceil(columns) as u16 + 2
+}
+
+pub fn chunk_index(wid: WidgetId) -> usize {
+    match wid {
+        WidgetId::Registers => 2,
+        WidgetId::HardReset => 6,
+        WidgetId::Ext => 7,
+        WidgetId::ExtPresets => 8,
+        WidgetId::Compile => 9,
+        WidgetId::Dac => 10,
+        WidgetId::Offsets => 11,
+        WidgetId::Control => 12,
+        WidgetId::Ssh => 13,
+        WidgetId::Terminal => 14,
+        WidgetId::Log => 15,
+        WidgetId::Audit => 16,
+    }
 }
 
-pub fn layout_init<B: Backend>(f: &mut Frame<B>) -> Vec<Rect> {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
+fn split(area: Rect, direction: Direction, constraints: &[Constraint]) -> Vec<Rect> {
+    Layout::default()
+        .direction(direction)
         .margin(0)
-        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
-        .split(f.size());
+        .constraints(constraints)
+        .split(area)
+}
+
+const SETTINGS: [WidgetId; 3] = [WidgetId::Dac, WidgetId::Offsets, WidgetId::Control];
+pub const PANES: [WidgetId; 4] = [WidgetId::Ssh, WidgetId::Terminal, WidgetId::Log, WidgetId::Audit];
+
+// every layout but the full one leaves some widgets to the tab bar
+fn tabbed(area: Rect, zoom: bool, tree: Option<&LayoutNode>) -> bool {
+    zoom || (tree.is_none() && layout_mode(area) != LayoutMode::Full)
+}
+
+pub fn layout_init(
+    area: Rect,
+    zoom: bool,
+    selected: WidgetId,
+    tree: Option<&LayoutNode>,
+) -> Vec<Rect> {
+    let chunks = split(
+        area,
+        Direction::Vertical,
+        &[Constraint::Length(status_height(area.width)), Constraint::Min(0)],
+    );
     let top_bar = chunks[0];
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .margin(0)
-        .constraints(
-            [
-                Constraint::Length(40),
-                Constraint::Length(40),
-                Constraint::Min(0),
-            ]
-            .as_ref(),
-        )
-        .split(chunks[1]);
-    let left_col = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints(
-            [
-                Constraint::Length(10),
-                Constraint::Length(40),
-                Constraint::Length(3),
-                Constraint::Min(0),
-                Constraint::Length(3),
-                Constraint::Length(3),
-            ]
-            .as_ref(),
-        )
-        .split(chunks[0]);
-    let ext_col = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints(
-            [
-                Constraint::Length(14),
+    let body = chunks[1];
+    let mut ret = vec![Rect::default(); 17];
+    ret[0] = top_bar;
+    if zoom {
+        let body = split(body, Direction::Vertical, &[Constraint::Min(0), Constraint::Length(1)]);
+        ret[chunk_index(selected)] = body[0];
+        ret.push(body[1]);
+        return ret;
+    }
+    if let Some(tree) = tree {
+        tree.split(body, &mut ret);
+        return ret;
+    }
+    let mode = layout_mode(area);
+    if mode == LayoutMode::Compact {
+        compact_layout(body, selected, &mut ret);
+        return ret;
+    }
+    let full = mode == LayoutMode::Full;
+    let columns = split(
+        body,
+        Direction::Horizontal,
+        &[
+            Constraint::Length(40),
+            Constraint::Length(40),
+            Constraint::Min(0),
+        ],
+    );
+    let left_col = split(
+        columns[0],
+        Direction::Vertical,
+        &[
+            Constraint::Length(10),
+            Constraint::Min(4),
+            Constraint::Length(3),
+            Constraint::Length(0),
+            Constraint::Length(3),
+            Constraint::Length(3),
+        ],
+    );
+    let ext_col = split(
+        columns[1],
+        Direction::Vertical,
+        &[
+            Constraint::Length(14),
+            Constraint::Min(3),
+            Constraint::Length(if full { 13 } else { 8 }),
+        ],
+    );
+    ret[1..7].copy_from_slice(&left_col);
+    ret[7..10].copy_from_slice(&ext_col);
+    if full {
+        let right_col = split(
+            columns[2],
+            Direction::Vertical,
+            &[
+                Constraint::Length(22),
                 Constraint::Min(0),
-                Constraint::Length(13),
-            ]
-            .as_ref(),
-        )
-        .split(chunks[1]);
-    let right_col = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints([Constraint::Length(22), Constraint::Min(0)].as_ref())
-        .split(chunks[2]);
-    let control_row = Layout::default()
-        .direction(Direction::Horizontal)
-        .margin(0)
-        .constraints(
-            [
+                Constraint::Length(12),
+            ],
+        );
+        let control_row = split(
+            right_col[0],
+            Direction::Horizontal,
+            &[
                 Constraint::Length(40),
                 Constraint::Length(42),
                 Constraint::Min(0),
-            ]
-            .as_ref(),
-        )
-        .split(right_col[0]);
-    let dac_col = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(0)
-        .constraints([Constraint::Length(12), Constraint::Length(10)].as_ref())
-        .split(control_row[0]);
-    let mut ret = Vec::new();
-    ret.push(top_bar);
-    ret.extend(left_col);
-    ret.extend(ext_col);
-    ret.extend(dac_col);
-    ret.extend(&control_row[1..]);
-    ret.extend(&right_col[1..]);
+            ],
+        );
+        let dac_col = split(
+            control_row[0],
+            Direction::Vertical,
+            &[Constraint::Length(12), Constraint::Length(10)],
+        );
+        ret[10..12].copy_from_slice(&dac_col);
+        ret[12..14].copy_from_slice(&control_row[1..]);
+        ret[14] = right_col[1];
+        let log_row = split(
+            right_col[2],
+            Direction::Horizontal,
+            &[Constraint::Percentage(50), Constraint::Percentage(50)],
+        );
+        ret[15..17].copy_from_slice(&log_row);
+    } else {
+        // one settings widget over one output pane, the tab bar reaches the rest
+        let right_col = split(
+            columns[2],
+            Direction::Vertical,
+            &[
+                Constraint::Ratio(1, 2),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ],
+        );
+        let settings = match SETTINGS.contains(&selected) {
+            true => selected,
+            false => WidgetId::Dac,
+        };
+        let pane = match PANES.contains(&selected) {
+            true => selected,
+            false => WidgetId::Ssh,
+        };
+        ret[chunk_index(settings)] = right_col[0];
+        ret[chunk_index(pane)] = right_col[1];
+        ret.push(right_col[2]);
+    }
     ret
 }
 
+// the readouts stay on screen, the selected widget takes the rest
+fn compact_layout(body: Rect, selected: WidgetId, ret: &mut Vec<Rect>) {
+    let (readouts, rest) = if body.width >= 80 {
+        let columns = split(
+            body,
+            Direction::Horizontal,
+            &[Constraint::Length(40), Constraint::Min(0)],
+        );
+        (columns[0], columns[1])
+    } else {
+        let rows = split(
+            body,
+            Direction::Vertical,
+            &[Constraint::Length(13), Constraint::Min(0)],
+        );
+        (rows[0], rows[1])
+    };
+    let readouts = split(
+        readouts,
+        Direction::Vertical,
+        &[Constraint::Length(10), Constraint::Length(3), Constraint::Min(0)],
+    );
+    let state_row = split(
+        readouts[1],
+        Direction::Horizontal,
+        &[Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)],
+    );
+    ret[1] = readouts[0];
+    ret[3] = state_row[0];
+    ret[5] = state_row[1];
+    let rest = split(rest, Direction::Vertical, &[Constraint::Min(0), Constraint::Length(1)]);
+    ret[chunk_index(selected)] = rest[0];
+    ret.push(rest[1]);
+}
+
+fn tab_bar<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let labels: Vec<String> = WIDGETS.iter().map(|wid| format!(" {} ", wid.name())).collect();
+    let selected = WIDGETS
+        .iter()
+        .position(|wid| state.is_widget_selected(*wid))
+        .unwrap_or(0);
+    let mut first = 0;
+    while labels[first..=selected].iter().map(String::len).sum::<usize>() > chunk.width as usize
+        && first < selected
+    {
+        first += 1;
+    }
+    let theme = *state.theme();
+    let mut spans = Vec::new();
+    let mut x = chunk.x;
+    for (wid, label) in WIDGETS.into_iter().zip(labels).skip(first) {
+        let width = (label.len() as u16).min((chunk.x + chunk.width).saturating_sub(x));
+        if width == 0 {
+            break;
+        }
+        state.set_tab(wid, Rect::new(x, chunk.y, width, 1));
+        x += width;
+        let style = if state.is_widget_selected(wid) {
+            theme.focus.add_modifier(Modifier::REVERSED)
+        } else {
+            theme.muted
+        };
+        spans.push(Span::styled(label, style));
+    }
+    f.render_widget(Paragraph::new(Spans::from(spans)), chunk);
+}
+
+fn too_small<B: Backend>(f: &mut Frame<B>) {
+    let area = f.size();
+    let text = format!(
+        "Terminal too small: {}x{}, need at least {}x{}",
+        area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+    );
+    let block = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    let y = area.height / 2;
+    f.render_widget(block, Rect::new(0, y, area.width, area.height - y));
+}
+
 fn status<B: Backend>(chunk: Rect, f: &mut Frame<B>, status: &mut StateKeeper) {
+    let theme = *status.theme();
     let reg = status.status_register();
     let values = bits(&reg);
-    let block = Table::new(vec![Row::new(vec![
-        Cell::from("SSPA_Active").style(Style::default().fg(values[0].color())),
-        Cell::from("HW_Reflected_Power").style(Style::default().fg(values[1].color())),
-        Cell::from("HW_Over_temperature").style(Style::default().fg(values[2].color())),
-        Cell::from("HW_Over_drive").style(Style::default().fg(values[3].color())),
-        Cell::from("HW_Gan1").style(Style::default().fg(values[4].color())),
-        Cell::from("HW_Gan2").style(Style::default().fg(values[5].color())),
-        Cell::from("HW_Gan3").style(Style::default().fg(values[6].color())),
-        Cell::from("HW_Gan4").style(Style::default().fg(values[7].color())),
-        Cell::from("SW_Reflected_Power").style(Style::default().fg(values[8].color())),
-        Cell::from("SW_Direct_Power").style(Style::default().fg(values[9].color())),
-        Cell::from("SW_Under_drive").style(Style::default().fg(values[10].color())),
-        Cell::from("SW_Over_drive").style(Style::default().fg(values[11].color())),
-        Cell::from("SW_Duty_Cycle").style(Style::default().fg(values[12].color())),
-        Cell::from("SW_Over_Temperature").style(Style::default().fg(values[13].color())),
-        Cell::from("SW_Over_Current").style(Style::default().fg(values[14].color())),
-    ])])
-    .style(Style::default().fg(reg.color()))
-    .block(Block::default().title("Status").borders(Borders::ALL))
-    .widths(&[
-        Constraint::Length(11),
-        Constraint::Length(18),
-        Constraint::Length(19),
-        Constraint::Length(13),
-        Constraint::Length(7),
-        Constraint::Length(7),
-        Constraint::Length(7),
-        Constraint::Length(7),
-        Constraint::Length(18),
-        Constraint::Length(15),
-        Constraint::Length(14),
-        Constraint::Length(13),
-        Constraint::Length(13),
-        Constraint::Length(19),
-        Constraint::Length(15),
-    ])
-    .column_spacing(4);
+    let connection_state = status.connection_state();
+    let mut title = Spans::from(vec![
+        Span::raw("Status"),
+        Span::styled(
+            format!(" SSH: {} ", connection_state),
+            connection_state.style(&theme),
+        ),
+        Span::raw(format!("Layout: {} ", status.layout_name())),
+    ]);
+    if status.read_only() {
+        title.0.push(Span::styled(
+            "READ-ONLY",
+            theme.warning.add_modifier(Modifier::REVERSED | Modifier::BOLD),
+        ));
+        title.0.push(Span::raw(" "));
+    }
+    if let Some(script) = status.script_running() {
+        title.0.push(Span::styled(
+            format!("Script: {} (Esc aborts) ", script),
+            theme.warning,
+        ));
+    }
+    if status.active_limits() > 0 {
+        title.0.push(Span::styled(
+            format!("Limits: {} tripped ", status.active_limits()),
+            theme.error.add_modifier(Modifier::REVERSED),
+        ));
+    }
+    let cells: Vec<Cell> = STATUS_BITS
+        .iter()
+        .zip(values)
+        .map(|(label, value)| Cell::from(*label).style(value.style(&theme)))
+        .collect();
+    let (rows, widths): (Vec<Row>, Vec<Constraint>) = if status_single_row(chunk.width) {
+        let widths = STATUS_BITS
+            .iter()
+            .map(|label| Constraint::Length(label.len() as u16))
+            .collect();
+        (vec![Row::new(cells)], widths)
+    } else {
+        let columns = status_columns(chunk.width).max(1);
+        let rows = cells.chunks(columns).map(|row| Row::new(row.to_vec())).collect();
+        (rows, vec![Constraint::Length(STATUS_CELL); columns])
+    };
+    let block = Table::new(rows)
+        .style(reg.style(&theme))
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .widths(&widths)
+        .column_spacing(STATUS_SPACING);
     f.render_widget(block, chunk);
 }
 
 fn adc_measurements<B: Backend>(chunk: Rect, f: &mut Frame<B>, status: &mut StateKeeper) {
+    let theme = *status.theme();
     let regs = status.adc_measurements();
+    let violated = status.limit_violations();
+    let style = |i: usize| match violated[i] {
+        true => theme.error.add_modifier(Modifier::REVERSED),
+        false => regs[i].style(&theme),
+    };
     let items = [
-        ListItem::new(format!("{:<15}:{:>20}", "Output Power", regs[0].value()))
-            .style(Style::default().fg(regs[0].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Reflected Power", regs[1].value()))
-            .style(Style::default().fg(regs[1].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Drive Level", regs[2].value()))
-            .style(Style::default().fg(regs[2].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Temperature", regs[3].value()))
-            .style(Style::default().fg(regs[3].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Gan 1 Current", regs[4].value()))
-            .style(Style::default().fg(regs[4].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Gan 2 Current", regs[5].value()))
-            .style(Style::default().fg(regs[5].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Gan 3 Current", regs[6].value()))
-            .style(Style::default().fg(regs[6].color())),
-        ListItem::new(format!("{:<15}:{:>20}", "Gan 4 Current", regs[7].value()))
-            .style(Style::default().fg(regs[7].color())),
+        ListItem::new(format!("{:<15}:{:>20}", "Output Power", register_value(&regs[0], &theme)))
+            .style(style(0)),
+        ListItem::new(format!("{:<15}:{:>20}", "Reflected Power", register_value(&regs[1], &theme)))
+            .style(style(1)),
+        ListItem::new(format!("{:<15}:{:>20}", "Drive Level", register_value(&regs[2], &theme)))
+            .style(style(2)),
+        ListItem::new(format!("{:<15}:{:>20}", "Temperature", register_value(&regs[3], &theme)))
+            .style(style(3)),
+        ListItem::new(format!("{:<15}:{:>20}", "Gan 1 Current", register_value(&regs[4], &theme)))
+            .style(style(4)),
+        ListItem::new(format!("{:<15}:{:>20}", "Gan 2 Current", register_value(&regs[5], &theme)))
+            .style(style(5)),
+        ListItem::new(format!("{:<15}:{:>20}", "Gan 3 Current", register_value(&regs[6], &theme)))
+            .style(style(6)),
+        ListItem::new(format!("{:<15}:{:>20}", "Gan 4 Current", register_value(&regs[7], &theme)))
+            .style(style(7)),
     ];
     let block = List::new(items)
         .block(
@@ -170,97 +420,92 @@ fn adc_measurements<B: Backend>(chunk: Rect, f: &mut Frame<B>, status: &mut Stat
                 .title("ADC Measurements")
                 .borders(Borders::ALL),
         )
-        .style(Style::default().fg(Color::White));
+        .style(theme.text);
     f.render_widget(block, chunk);
 }
 
 fn registers<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
     let regs = state.thresholds();
     let items = [
         ListItem::new(format!(
             "Over Temperature Threshold\n{:^30}\n\n",
-            regs[0].value()
+            register_value(&regs[0], &theme)
         ))
-        .style(Style::default().fg(regs[0].color())),
+        .style(regs[0].style(&theme)),
         ListItem::new(format!(
             "Temperature Threshold Hysteresis\n{:^30}\n\n",
-            regs[1].value()
+            register_value(&regs[1], &theme)
         ))
-        .style(Style::default().fg(regs[1].color())),
+        .style(regs[1].style(&theme)),
         ListItem::new(format!(
             "Over Current Threshold\n{:^30}\n\n",
-            regs[2].value()
+            register_value(&regs[2], &theme)
         ))
-        .style(Style::default().fg(regs[2].color())),
+        .style(regs[2].style(&theme)),
         ListItem::new(format!(
             "Duty Cylce protection Threshold\n{:^30}\n\n",
-            regs[3].value()
+            register_value(&regs[3], &theme)
         ))
-        .style(Style::default().fg(regs[3].color())),
+        .style(regs[3].style(&theme)),
         ListItem::new(format!(
             "Pulse Length protection Threshold\n{:^30}\n\n",
-            regs[4].value()
+            register_value(&regs[4], &theme)
         ))
-        .style(Style::default().fg(regs[4].color())),
+        .style(regs[4].style(&theme)),
         ListItem::new(format!(
             "Over Drive protection Threshold\n{:^30}\n\n",
-            regs[5].value()
+            register_value(&regs[5], &theme)
         ))
-        .style(Style::default().fg(regs[5].color())),
+        .style(regs[5].style(&theme)),
         ListItem::new(format!(
             "Under Drive alarm Threshold\n{:^30}\n\n",
-            regs[6].value()
+            register_value(&regs[6], &theme)
         ))
-        .style(Style::default().fg(regs[6].color())),
+        .style(regs[6].style(&theme)),
         ListItem::new(format!(
             "Output Power protection Threshold\n{:^30}\n\n",
-            regs[7].value()
+            register_value(&regs[7], &theme)
         ))
-        .style(Style::default().fg(regs[7].color())),
+        .style(regs[7].style(&theme)),
         ListItem::new(format!(
             "Reflected Power protection Threshold\n{:^30}\n\n",
-            regs[8].value()
+            register_value(&regs[8], &theme)
         ))
-        .style(Style::default().fg(regs[8].color())),
-        ListItem::new(format!("SSPA serial number\n{:^30}\n\n", regs[9].value()))
-            .style(Style::default().fg(regs[9].color())),
+        .style(regs[8].style(&theme)),
+        ListItem::new(format!(
+            "SSPA serial number\n{:^30}\n\n",
+            register_value(&regs[9], &theme)
+        ))
+        .style(regs[9].style(&theme)),
     ];
     selectable_widget(WidgetId::Registers, "Registers", &items, state, chunk, f);
 }
 
 fn state<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
     let state = state.sspa_state();
-    let state_str = match state {
-        SSPAState::Invalid => "Invalid",
-        SSPAState::Boot => "Boot",
-        SSPAState::StandBy => "StandBy",
-        SSPAState::Failure => "Failure",
-        SSPAState::Disabled => "Disabled",
-        SSPAState::Nominal => "Nominal",
-        SSPAState::Warning => "Warning",
-        SSPAState::ProtectionHW => "ProtectionHW",
-        SSPAState::Protection => "Protection",
-    };
     let text = vec![Spans::from(Span::styled(
-        state_str,
-        Style::default().fg(state.color()),
+        marked(state, state.symbol(), &theme),
+        state.style(&theme),
     ))];
     let block = Paragraph::new(text)
         .block(Block::default().title("State").borders(Borders::ALL))
-        .style(Style::default().fg(Color::White))
+        .style(theme.text)
         .alignment(Alignment::Center)
         .wrap(Wrap { trim: true });
     f.render_widget(block, chunk);
 }
 
 fn firmware_version<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
     let vernum = state.version_number();
     let mayor = (vernum.value() >> 8) & 0x7F;
     let minor = (vernum.value() >> 8) & 0x0F;
     let patch = (vernum.value() >> 8) & 0x0F;
     let text = vec![Spans::from(Span::styled(
-        format!("v{}.{}.{}", mayor, minor, patch),
-        Style::default().fg(vernum.color()),
+        marked(format!("v{}.{}.{}", mayor, minor, patch), vernum.symbol(), &theme),
+        vernum.style(&theme),
     ))];
     let block = Paragraph::new(text)
         .block(
@@ -268,35 +513,34 @@ fn firmware_version<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut State
                 .title("Firmware Version")
                 .borders(Borders::ALL),
         )
-        .style(Style::default().fg(Color::White))
+        .style(theme.text)
         .alignment(Alignment::Center)
         .wrap(Wrap { trim: true });
     f.render_widget(block, chunk);
 }
 
 fn hard_reset<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
-    let text = vec![Spans::from(Span::styled(
-        "HARD RESET",
-        Style::default().fg(Color::Red),
-    ))];
+    let theme = *state.theme();
+    let style = match state.read_only() {
+        true => theme.error.add_modifier(Modifier::DIM),
+        false => theme.error,
+    };
+    let text = vec![Spans::from(Span::styled("HARD RESET", style))];
+    let selected = state.is_widget_selected(WidgetId::HardReset);
     let block = Paragraph::new(text)
-        .block(Block::default().title("Hard Reset").borders(Borders::ALL))
-        .style(
-            Style::default().fg(if state.is_widget_selected(WidgetId::HardReset) {
-                Color::Green
-            } else {
-                Color::White
-            }),
-        )
+        .block(widget_block("Hard Reset", selected, &theme))
+        .style(focus_style(selected, &theme))
         .alignment(Alignment::Center)
         .wrap(Wrap { trim: true });
     f.render_widget(block, chunk);
+    state.set_area(WidgetId::HardReset, chunk, vec![chunk.height.saturating_sub(2) as usize], 0);
 }
 
 fn ext_signals<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
     let (powen, current, values) = state.ext_signals();
     let items = [
-        ListItem::new("Power Enable").style(Style::default().fg(powen.color())),
+        ListItem::new("Power Enable").style(powen.style(&theme)),
         ListItem::new(format!("TnR:\n{:>12}:{:?}", "Current", current)),
         ListItem::new(format!("{:>12}:{:>20}", "Period", values[0])),
         ListItem::new(format!("{:>12}:{:>20}", "Pulse Width", values[1])),
@@ -309,7 +553,11 @@ fn ext_signals<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeepe
 }
 
 fn ext_signals_presets<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
-    let items = [];
+    let items: Vec<ListItem> = state
+        .presets()
+        .iter()
+        .map(|(name, preset)| ListItem::new(format!("{} {:?}", name, preset)))
+        .collect();
     selectable_widget(
         WidgetId::ExtPresets,
         "Ext Signals Presets",
@@ -352,26 +600,27 @@ fn offsets<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
 }
 
 fn control<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
     let reg = state.control_register();
     let values = bits(&reg);
     let items = [
         ListItem::new(format!("\n{:^40}", "[Store to Non Volatile Memory]"))
-            .style(Style::default().fg(reg.color())),
+            .style(reg.style(&theme)),
         ListItem::new(format!("\n{:^40}", "[Load from Non Volatile Memory]"))
-            .style(Style::default().fg(reg.color())),
-        ListItem::new(format!("\n{:^40}", "[Alarms Reset]")).style(Style::default().fg(reg.color())),
-        ListItem::new(format!("\n{:^40}", "[SSPA Reset]")).style(Style::default().fg(reg.color())),
-        ListItem::new(format!("\n{:^40}", "[SSPA Disable]")).style(Style::default().fg(reg.color())),
+            .style(reg.style(&theme)),
+        ListItem::new(format!("\n{:^40}", "[Alarms Reset]")).style(reg.style(&theme)),
+        ListItem::new(format!("\n{:^40}", "[SSPA Reset]")).style(reg.style(&theme)),
+        ListItem::new(format!("\n{:^40}", "[SSPA Disable]")).style(reg.style(&theme)),
         ListItem::new(format!("\n{:^40}", "SW Reflected Power protection disable"))
-            .style(Style::default().fg(values[10].color())),
+            .style(values[10].style(&theme)),
         ListItem::new(format!("{:^40}", "SW Over drive protection disable"))
-            .style(Style::default().fg(values[11].color())),
+            .style(values[11].style(&theme)),
         ListItem::new(format!("{:^40}", "SW Duty cycle protection disable"))
-            .style(Style::default().fg(values[12].color())),
+            .style(values[12].style(&theme)),
         ListItem::new(format!("{:^40}", "SW Over temperature protection disable"))
-            .style(Style::default().fg(values[13].color())),
+            .style(values[13].style(&theme)),
         ListItem::new(format!("{:^40}", "SW Over current protection disable"))
-            .style(Style::default().fg(values[14].color())),
+            .style(values[14].style(&theme)),
     ];
     selectable_widget(WidgetId::Control, "Control", &items, state, chunk, f);
 }
@@ -387,42 +636,265 @@ fn compile<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
     selectable_widget(WidgetId::Compile, "Compile", &items, state, chunk, f);
 }
 
-fn terminal<B: Backend>(chunk: Rect, f: &mut Frame<B>, term: &mut Launcher) {
-    let text = Text::from(term.read());
-    let block = Paragraph::new(text)
-        .block(Block::default().title("Terminal").borders(Borders::ALL))
-        .style(Style::default().fg(Color::White))
-        .alignment(Alignment::Left)
+fn terminal<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    pane(WidgetId::Terminal, "Terminal", state, chunk, f);
+}
+
+fn ssh<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    pane(WidgetId::Ssh, "SSH", state, chunk, f);
+}
+
+fn event_log<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    pane(WidgetId::Log, "Event Log", state, chunk, f);
+}
+
+fn audit_log<B: Backend>(chunk: Rect, f: &mut Frame<B>, state: &mut StateKeeper) {
+    pane(WidgetId::Audit, "Audit Log", state, chunk, f);
+}
+
+pub fn ui<B: Backend>(f: &mut Frame<B>, state_keeper: &mut StateKeeper) {
+    state_keeper.clear_areas();
+    let area = f.size();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        too_small(f);
+        return;
+    }
+    let zoom = state_keeper.zoom();
+    let tabbed = tabbed(area, zoom, state_keeper.layout());
+    state_keeper.set_tabbed(tabbed);
+    let chunks = layout_init(
+        area,
+        zoom,
+        state_keeper.selected_widget(),
+        state_keeper.layout(),
+    );
+    let widgets: [(usize, Draw<B>); 16] = [
+        (0, status),
+        (1, adc_measurements),
+        (2, registers),
+        (3, state),
+        (5, firmware_version),
+        (6, hard_reset),
+        (7, ext_signals),
+        (8, ext_signals_presets),
+        (9, compile),
+        (10, dac),
+        (11, offsets),
+        (12, control),
+        (13, ssh),
+        (14, terminal),
+        (15, event_log),
+        (16, audit_log),
+    ];
+    for (index, draw) in widgets {
+        if chunks[index].area() > 0 {
+            draw(chunks[index], f, state_keeper);
+        }
+    }
+    if let Some(chunk) = chunks.get(17) {
+        tab_bar(*chunk, f, state_keeper);
+    }
+    if state_keeper.help() {
+        help(f, state_keeper);
+    }
+    if state_keeper.snapshot_diff().is_some() {
+        snapshot_diff(f, state_keeper);
+    }
+    if state_keeper.sign_off().is_some() {
+        sign_off(f, state_keeper);
+    }
+    if let Some(input) = state_keeper.palette_input() {
+        palette(input.to_string(), f, state_keeper);
+    }
+}
+
+fn palette<B: Backend>(input: String, f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
+    let area = f.size();
+    let chunk = Rect {
+        x: area.width / 4,
+        y: area.height / 8,
+        width: area.width / 2,
+        height: (area.height * 3 / 4).min(24),
+    };
+    let selected = state.palette_selected();
+    let visible = chunk.height.saturating_sub(3) as usize;
+    let skip = (selected + 1).saturating_sub(visible);
+    let mut lines = vec![Spans::from(vec![
+        Span::styled(":", theme.accent),
+        Span::raw(format!("{}_", input)),
+    ])];
+    for (i, (command, arg)) in state.palette_matches().into_iter().enumerate().skip(skip).take(visible) {
+        let style = if i == selected {
+            Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        let arg = arg.unwrap_or_else(|| command.arg.hint().to_string());
+        lines.push(Spans::from(vec![
+            Span::styled(command.name, style),
+            Span::styled(format!(" {}", arg), theme.muted),
+        ]));
+    }
+    let block = Paragraph::new(lines).block(
+        Block::default()
+            .title(" Command Palette ")
+            .borders(Borders::ALL),
+    );
+    f.render_widget(Clear, chunk);
+    f.render_widget(block, chunk);
+}
+
+fn help<B: Backend>(f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
+    let area = f.size();
+    let chunk = Rect {
+        x: area.width / 6,
+        y: area.height / 10,
+        width: area.width * 2 / 3,
+        height: area.height * 4 / 5,
+    };
+    let mut lines = Vec::new();
+    let mut context = None;
+    for binding in state.keymap().bindings() {
+        if context != Some(binding.context) {
+            context = Some(binding.context);
+            lines.push(Spans::from(Span::styled(
+                format!("{} keys", binding.context.name()),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+        }
+        lines.push(Spans::from(vec![
+            Span::raw(format!("  {:<20}", binding.name)),
+            Span::styled(binding.keys.join(", "), theme.accent),
+        ]));
+    }
+    let wid = state.selected_widget();
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        format!("{} items", wid.name()),
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    for item in 0..state.item_count(wid) {
+        if let Some(action) = item_action(wid, item) {
+            lines.push(Spans::from(format!("  {:<20}{}", item, action)));
+        }
+    }
+    let block = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(" Help (any key to close) ")
+                .borders(Borders::ALL),
+        )
         .wrap(Wrap { trim: false });
+    f.render_widget(Clear, chunk);
     f.render_widget(block, chunk);
 }
 
-fn ssh<B: Backend>(chunk: Rect, f: &mut Frame<B>, ssh_term: &mut Launcher) {
-    let text = Text::from(ssh_term.read());
-    let block = Paragraph::new(text)
-        .block(Block::default().title("SSH").borders(Borders::ALL))
-        .style(Style::default().fg(Color::White))
-        .alignment(Alignment::Left)
+fn snapshot_diff<B: Backend>(f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
+    let (diff, scroll) = match state.snapshot_diff() {
+        Some(diff) => diff,
+        None => return,
+    };
+    let area = f.size();
+    let chunk = Rect {
+        x: area.width / 10,
+        y: area.height / 10,
+        width: area.width * 4 / 5,
+        height: area.height * 4 / 5,
+    };
+    let field = diff.rows.iter().map(|row| row.field.len()).max().unwrap_or(0) + 2;
+    let column = (chunk.width as usize).saturating_sub(field + 4) / 2;
+    let mut lines = vec![Spans::from(Span::styled(
+        format!("  {:<field$}{:<column$}{}", "FIELD", diff.old, diff.new),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    for row in diff.rows.iter().skip(scroll) {
+        let (symbol, style) = match row.changed() {
+            true => ("*", theme.warning.add_modifier(Modifier::BOLD)),
+            false => (" ", theme.text),
+        };
+        lines.push(Spans::from(Span::styled(
+            format!("{} {:<field$}{:<column$}{}", symbol, row.field, row.old, row.new),
+            style,
+        )));
+    }
+    let title = match state.restore_pending() {
+        true => format!(
+            " Restore preview: {} changes (Enter writes, s writes and stores to NVM, any other key cancels) ",
+            diff.changed()
+        ),
+        false => format!(
+            " Snapshot diff: {} changed (Up/Down scroll, any other key to close) ",
+            diff.changed()
+        ),
+    };
+    let block = Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL));
+    f.render_widget(Clear, chunk);
+    f.render_widget(block, chunk);
+}
+
+fn sign_off<B: Backend>(f: &mut Frame<B>, state: &mut StateKeeper) {
+    let theme = *state.theme();
+    let report = match state.sign_off() {
+        Some(report) => report,
+        None => return,
+    };
+    let area = f.size();
+    let chunk = Rect {
+        x: area.width / 4,
+        y: area.height / 3,
+        width: area.width / 2,
+        height: 7.min(area.height),
+    };
+    let verdict_style = match report.verdict() {
+        "PASS" => theme.ok,
+        _ => theme.error,
+    };
+    let lines = vec![
+        Spans::from(format!("{} on unit {}", report.plan(), report.serial_number())),
+        Spans::from(vec![
+            Span::raw("Verdict: "),
+            Span::styled(report.verdict().to_string(), verdict_style.add_modifier(Modifier::BOLD)),
+        ]),
+        Spans::from(""),
+        Spans::from(format!("Enter signs the report as {}, Esc leaves it unsigned", user_name())),
+    ];
+    let block = Paragraph::new(lines)
+        .block(Block::default().title(" Test sign-off ").borders(Borders::ALL))
         .wrap(Wrap { trim: false });
+    f.render_widget(Clear, chunk);
     f.render_widget(block, chunk);
 }
 
-pub fn ui<B: Backend>(f: &mut Frame<B>, state_keeper: &mut StateKeeper, term: &mut Launcher, ssh_term: &mut Launcher) {
-    let chunks = layout_init(f);
-    status(chunks[0], f, state_keeper);
-    adc_measurements(chunks[1], f, state_keeper);
-    registers(chunks[2], f, state_keeper);
-    state(chunks[3], f, state_keeper);
-    firmware_version(chunks[5], f, state_keeper);
-    hard_reset(chunks[6], f, state_keeper);
-    ext_signals(chunks[7], f, state_keeper);
-    ext_signals_presets(chunks[8], f, state_keeper);
-    compile(chunks[9], f, state_keeper);
-    dac(chunks[10], f, state_keeper);
-    offsets(chunks[11], f, state_keeper);
-    control(chunks[12], f, state_keeper);
-    ssh(chunks[13], f, ssh_term);
-    terminal(chunks[14], f, term);
+fn focus_style(selected: bool, theme: &Theme) -> Style {
+    if selected {
+        theme.focus
+    } else {
+        theme.text
+    }
+}
+
+fn widget_block<'a>(title: impl Into<Spans<'a>>, selected: bool, theme: &Theme) -> Block<'a> {
+    let block = Block::default().title(title).borders(Borders::ALL);
+    if selected {
+        block.border_type(theme.focus_border)
+    } else {
+        block
+    }
+}
+
+fn marked(text: impl std::fmt::Display, symbol: &str, theme: &Theme) -> String {
+    if theme.symbols && !symbol.is_empty() {
+        format!("{} {}", symbol, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn register_value(reg: &Register, theme: &Theme) -> String {
+    marked(reg.value(), reg.symbol(), theme)
 }
 
 fn selectable_widget<B: Backend>(
@@ -433,13 +905,280 @@ fn selectable_widget<B: Backend>(
     chunk: Rect,
     f: &mut Frame<B>,
 ) {
+    let title = match state.edit_input() {
+        Some(input) if state.is_widget_selected(wid) => format!("{} [{}_]", title, input),
+        _ => title.to_string(),
+    };
+    let theme = *state.theme();
+    let selected = state.is_widget_selected(wid);
     let block = List::new(items)
-        .block(Block::default().title(title).borders(Borders::ALL))
-        .style(Style::default().fg(if state.is_widget_selected(wid) {
-            Color::Green
-        } else {
-            Color::White
-        }))
+        .block(widget_block(title, selected, &theme))
+        .style(focus_style(selected, &theme))
         .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+    let heights: Vec<usize> = items.iter().map(ListItem::height).collect();
+    let selected = state.selected_item(wid).selected();
+    let offset = list_offset(
+        &heights,
+        selected,
+        state.list_offset(wid),
+        chunk.height.saturating_sub(2) as usize,
+    );
     f.render_stateful_widget(block, chunk, state.selected_item(wid));
+    if state.read_only() {
+        // patch the rendered rows so the item colors still show the unit state
+        let dim = Block::default().style(Style::default().add_modifier(Modifier::DIM));
+        let inner = chunk.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        });
+        let mut y = inner.y;
+        for (item, height) in heights.iter().enumerate().skip(offset) {
+            if y >= inner.bottom() {
+                break;
+            }
+            let height = (*height as u16).min(inner.bottom() - y);
+            if item_action(wid, item).is_some_and(|action| action.writes()) {
+                f.render_widget(dim.clone(), Rect::new(inner.x, y, inner.width, height));
+            }
+            y += height;
+        }
+    }
+    state.set_area(wid, chunk, heights, offset);
+}
+
+fn list_offset(heights: &[usize], selected: Option<usize>, offset: usize, max_height: usize) -> usize {
+    if heights.is_empty() {
+        return 0;
+    }
+    let mut start = offset.min(heights.len() - 1);
+    let mut end = start;
+    let mut height = 0;
+    for item in &heights[start..] {
+        if height + item > max_height {
+            break;
+        }
+        height += item;
+        end += 1;
+    }
+    let selected = selected.unwrap_or(0).min(heights.len() - 1);
+    while selected >= end {
+        height += heights[end];
+        end += 1;
+        while height > max_height {
+            height -= heights[start];
+            start += 1;
+        }
+    }
+    while selected < start {
+        start -= 1;
+        height += heights[start];
+        while height > max_height {
+            end -= 1;
+            height -= heights[end];
+        }
+    }
+    start
+}
+
+fn pane<B: Backend>(
+    wid: WidgetId,
+    title: &str,
+    state: &mut StateKeeper,
+    chunk: Rect,
+    f: &mut Frame<B>,
+) {
+    let theme = *state.theme();
+    let selected = state.is_widget_selected(wid);
+    let search_input = state.search_input().filter(|_| selected).map(str::to_string);
+    state.set_area(wid, chunk, Vec::new(), 0);
+    let scrollback = match state.pane_mut(wid) {
+        Some(scrollback) => scrollback,
+        None => return,
+    };
+    let width = chunk.width.saturating_sub(2).max(1) as usize;
+    let height = chunk.height.saturating_sub(2) as usize;
+    scrollback.set_viewport(height);
+
+    let mut title = vec![Span::raw(title.to_string())];
+    if !scrollback.is_following() {
+        title.push(Span::styled(
+            format!(" [SCROLL -{}]", scrollback.scroll()),
+            theme.accent,
+        ));
+    }
+    match scrollback.filter() {
+        Some(Stream::Stdout) => title.push(Span::raw(" [stdout]")),
+        Some(Stream::Stderr) => title.push(Span::raw(" [stderr]")),
+        None => {}
+    }
+    if scrollback.is_hex() {
+        title.push(Span::raw(" [hex]"));
+    }
+    match (search_input, scrollback.search()) {
+        (Some(input), _) => title.push(Span::raw(format!(" /{}_", input))),
+        (None, Some(search)) => title.push(Span::raw(format!(
+            " /{} ({} matches)",
+            search.as_str(),
+            scrollback.match_count()
+        ))),
+        _ => {}
+    }
+
+    let (lines, overflow) = visible_lines(scrollback, width, height, &theme);
+    let block = Paragraph::new(Text::from(lines))
+        .block(widget_block(Spans::from(title), selected, &theme))
+        .style(focus_style(selected, &theme))
+        .alignment(Alignment::Left)
+        .wrap(Wrap { trim: false })
+        .scroll((overflow, 0));
+    f.render_widget(block, chunk);
+}
+
+fn visible_lines(
+    scrollback: &Scrollback,
+    width: usize,
+    height: usize,
+    theme: &Theme,
+) -> (Vec<Spans<'static>>, u16) {
+    let prefix = |line: &Line| {
+        if scrollback.show_timestamps() {
+            format!("[{:>6} {:>10.3}] ", line.seq, line.timestamp.as_secs_f64())
+        } else {
+            String::new()
+        }
+    };
+    // walk up from the bottom of the view until the pane is full
+    let mut rows = 0;
+    let mut window: Vec<(u64, &Line)> = scrollback
+        .lines()
+        .rev()
+        .skip(scrollback.scroll())
+        .take_while(|(_, line)| {
+            if rows >= height {
+                return false;
+            }
+            let chars = prefix(line).len() + line_text(line, scrollback).chars().count();
+            rows += chars.max(1).div_ceil(width);
+            true
+        })
+        .collect();
+    window.reverse();
+    let spans = window
+        .iter()
+        .map(|(seq, line)| {
+            let mut spans = vec![Span::styled(prefix(line), theme.muted)];
+            spans.extend(highlight(
+                line,
+                scrollback,
+                scrollback.current_match() == Some(*seq),
+                theme,
+            ));
+            Spans::from(spans)
+        })
+        .collect();
+    (spans, rows.saturating_sub(height) as u16)
+}
+
+fn line_text(line: &Line, scrollback: &Scrollback) -> String {
+    if scrollback.is_hex() {
+        line.hex()
+    } else {
+        line.display_text().to_string()
+    }
+}
+
+fn highlight(
+    line: &Line,
+    scrollback: &Scrollback,
+    current: bool,
+    theme: &Theme,
+) -> Vec<Span<'static>> {
+    let text = line_text(line, scrollback);
+    let text = text.as_str();
+    let style = match line.stream {
+        Stream::Stdout => Style::default(),
+        Stream::Stderr => theme.stderr,
+    };
+    let search = match scrollback.search() {
+        Some(search) => search,
+        None => return vec![Span::styled(text.to_string(), style)],
+    };
+    let match_style = if current {
+        theme.current_match
+    } else {
+        theme.search_match
+    };
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for m in search.find_iter(text) {
+        spans.push(Span::styled(text[last..m.start()].to_string(), style));
+        spans.push(Span::styled(m.as_str().to_string(), match_style));
+        last = m.end();
+    }
+    spans.push(Span::styled(text[last..].to_string(), style));
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inside(inner: Rect, outer: Rect) -> bool {
+        inner.x >= outer.x
+            && inner.y >= outer.y
+            && inner.right() <= outer.right()
+            && inner.bottom() <= outer.bottom()
+    }
+
+    #[test]
+    fn layout_mode_follows_the_terminal_size() {
+        assert!(layout_mode(Rect::new(0, 0, 160, 45)) == LayoutMode::Full);
+        assert!(layout_mode(Rect::new(0, 0, 200, 44)) == LayoutMode::Medium);
+        assert!(layout_mode(Rect::new(0, 0, 120, 30)) == LayoutMode::Medium);
+        assert!(layout_mode(Rect::new(0, 0, 119, 60)) == LayoutMode::Compact);
+        assert!(tabbed(Rect::new(0, 0, 100, 30), false, None));
+        assert!(!tabbed(Rect::new(0, 0, 160, 45), false, None));
+        assert!(tabbed(Rect::new(0, 0, 160, 45), true, None));
+    }
+
+    #[test]
+    fn wide_compact_layout_keeps_the_readouts_beside_the_selected_widget() {
+        let area = Rect::new(0, 0, 100, 28);
+        let chunks = layout_init(area, false, WidgetId::Dac, None);
+        assert_eq!(chunks.len(), 18);
+        let tab_bar = chunks[17];
+        assert_eq!((tab_bar.height, tab_bar.bottom()), (1, area.bottom()));
+        for index in [1, 3, 5] {
+            assert!(chunks[index].area() > 0 && chunks[index].right() <= 40, "{}", index);
+        }
+        let selected = chunks[chunk_index(WidgetId::Dac)];
+        assert_eq!((selected.x, selected.right()), (40, 100));
+        assert_eq!(selected.bottom(), tab_bar.y);
+        for wid in WIDGETS.into_iter().filter(|wid| *wid != WidgetId::Dac) {
+            assert_eq!(chunks[chunk_index(wid)].area(), 0, "{:?}", wid);
+        }
+    }
+
+    #[test]
+    fn narrow_compact_layout_stacks_the_readouts_above_the_selected_widget() {
+        let area = Rect::new(0, 0, 60, 40);
+        let chunks = layout_init(area, false, WidgetId::Log, None);
+        let selected = chunks[chunk_index(WidgetId::Log)];
+        assert_eq!(selected.width, 60);
+        for index in [1, 3, 5] {
+            assert!(chunks[index].bottom() <= selected.y, "{}", index);
+        }
+        assert_eq!(chunks[3].width + chunks[5].width, 60);
+        assert!(WIDGETS.into_iter().all(|wid| inside(chunks[chunk_index(wid)], area)));
+    }
+
+    #[test]
+    fn medium_layout_shows_the_selected_settings_and_pane() {
+        let chunks = layout_init(Rect::new(0, 0, 130, 35), false, WidgetId::Terminal, None);
+        assert!(chunks[chunk_index(WidgetId::Terminal)].area() > 0);
+        assert!(chunks[chunk_index(WidgetId::Dac)].area() > 0);
+        assert_eq!(chunks[chunk_index(WidgetId::Ssh)].area(), 0);
+        assert_eq!(chunks[chunk_index(WidgetId::Control)].area(), 0);
+        assert!(chunks[chunk_index(WidgetId::Registers)].area() > 0);
+    }
 }